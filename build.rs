@@ -1,10 +1,20 @@
-//! Build script to get the RTT buffer size.
+//! Build script to get the RTT configuration, the crash ring share and the word size.
 
 use std::{env, path::PathBuf};
 
 #[allow(clippy::disallowed_methods)]
 fn main() {
     println!("cargo:rerun-if-env-changed=DEFMT_RTT_BUFFER_SIZE");
+    println!("cargo:rerun-if-env-changed=DEFMT_PERSIST_CRASH_PERCENT");
+    println!("cargo:rerun-if-env-changed=DEFMT_PERSIST_WORD_SIZE");
+    for var in [
+        "DEFMT_PERSIST_RTT_UP_CHANNELS",
+        "DEFMT_PERSIST_RTT_UP_BUFFER_SIZE",
+        "DEFMT_PERSIST_RTT_DOWN_CHANNELS",
+        "DEFMT_PERSIST_RTT_DOWN_BUFFER_SIZE",
+    ] {
+        println!("cargo:rerun-if-env-changed={var}");
+    }
 
     let size = env::var("DEFMT_RTT_BUFFER_SIZE")
         .map(|s| {
@@ -15,6 +25,52 @@ fn main() {
 
     assert!(size >= 32, "DEFMT_RTT_BUFFER_SIZE must be at least 32");
 
+    let usize_var = |name: &str, default: usize| {
+        env::var(name)
+            .map(|s| {
+                s.parse()
+                    .unwrap_or_else(|_| panic!("could not parse {name} as usize"))
+            })
+            .unwrap_or(default)
+    };
+    let up_channels = usize_var("DEFMT_PERSIST_RTT_UP_CHANNELS", 0);
+    let up_size = usize_var("DEFMT_PERSIST_RTT_UP_BUFFER_SIZE", 256);
+    let down_channels = usize_var("DEFMT_PERSIST_RTT_DOWN_CHANNELS", 0);
+    let down_size = usize_var("DEFMT_PERSIST_RTT_DOWN_BUFFER_SIZE", 64);
+
+    assert!(
+        up_channels <= 15 && down_channels <= 16,
+        "at most 15 extra RTT up channels and 16 down channels are supported"
+    );
+    assert!(
+        up_size >= 16 && down_size >= 16,
+        "DEFMT_PERSIST_RTT_UP_BUFFER_SIZE and DEFMT_PERSIST_RTT_DOWN_BUFFER_SIZE must be at least 16"
+    );
+
+    let crash_percent = env::var("DEFMT_PERSIST_CRASH_PERCENT")
+        .map(|s| {
+            s.parse()
+                .expect("could not parse DEFMT_PERSIST_CRASH_PERCENT as usize")
+        })
+        .unwrap_or(25_usize);
+
+    assert!(
+        (1..=90).contains(&crash_percent),
+        "DEFMT_PERSIST_CRASH_PERCENT must be between 1 and 90"
+    );
+
+    let word_size = usize_var("DEFMT_PERSIST_WORD_SIZE", 4);
+    let word = match word_size {
+        4 => "u32",
+        8 => "u64",
+        _ => panic!("DEFMT_PERSIST_WORD_SIZE must be 4 or 8"),
+    };
+    // With 64-bit words, each header field gets a word of its own.
+    println!("cargo::rustc-check-cfg=cfg(word64)");
+    if word_size == 8 && env::var_os("CARGO_FEATURE_WORD_WRITE").is_some() {
+        println!("cargo::rustc-cfg=word64");
+    }
+
     let out_dir_path = PathBuf::from(env::var_os("OUT_DIR").unwrap());
     let out_file_path = out_dir_path.join("consts.rs");
 
@@ -25,8 +81,54 @@ fn main() {
             ///
             /// Can be customized by setting the `DEFMT_RTT_BUFFER_SIZE` environment variable.
             /// Use a power of 2 for best performance.
-            pub(crate) const BUF_SIZE: usize = {};",
-            size
+            pub(crate) const BUF_SIZE: usize = {};
+
+            /// Number of RTT up channels after the defmt channel (default: 0).
+            ///
+            /// Can be customized by setting the `DEFMT_PERSIST_RTT_UP_CHANNELS` environment variable.
+            pub(crate) const EXTRA_UP_CHANNELS: usize = {};
+
+            /// Buffer size of each extra RTT up channel (default: 256).
+            ///
+            /// Can be customized by setting the `DEFMT_PERSIST_RTT_UP_BUFFER_SIZE` environment variable.
+            pub(crate) const UP_BUF_SIZE: usize = {};
+
+            /// Number of RTT down channels (default: 0).
+            ///
+            /// Can be customized by setting the `DEFMT_PERSIST_RTT_DOWN_CHANNELS` environment variable.
+            pub(crate) const DOWN_CHANNELS: usize = {};
+
+            /// Buffer size of each RTT down channel (default: 64).
+            ///
+            /// Can be customized by setting the `DEFMT_PERSIST_RTT_DOWN_BUFFER_SIZE` environment
+            /// variable.
+            pub(crate) const DOWN_BUF_SIZE: usize = {};",
+            size, up_channels, up_size, down_channels, down_size
+        ),
+    )
+    .unwrap();
+
+    std::fs::write(
+        out_dir_path.join("crash_ring.rs"),
+        format!(
+            "/// Share of the persist region used by the crash ring, in percent (default: 25).
+            ///
+            /// Can be customized by setting the `DEFMT_PERSIST_CRASH_PERCENT` environment variable.
+            const CRASH_PERCENT: usize = {};",
+            crash_percent
+        ),
+    )
+    .unwrap();
+
+    std::fs::write(
+        out_dir_path.join("word.rs"),
+        format!(
+            "/// Unit of the writes to the ring buffer with the `word-write` feature (default: `u32`).
+            ///
+            /// Can be customized by setting the `DEFMT_PERSIST_WORD_SIZE` environment variable to 4
+            /// or 8 bytes.
+            pub(crate) type Word = {};",
+            word
         ),
     )
     .unwrap();
@@ -0,0 +1,67 @@
+//! Decoding of the recovered defmt stream.
+
+use anyhow::{Context, Result};
+use defmt_decoder::{DecodeError, Frame, Locations, Table};
+
+/// The frames decoded from a defmt stream.
+#[derive(Debug, Default)]
+pub struct Decoded {
+    /// The decoded frames, formatted as `[LEVEL] message`, with the timestamp in front if the
+    /// firmware has one.
+    pub frames: Vec<String>,
+    /// Number of malformed frames skipped, e.g. from memory corrupted while persisted.
+    pub malformed: usize,
+}
+
+/// Decodes `stream` with the defmt table from `elf`.
+///
+/// With `locations`, each frame is followed by a line with its source location, if the ELF
+/// file has debug info. Malformed frames are skipped and counted.
+pub fn decode(elf: &[u8], stream: &[u8], locations: bool) -> Result<Decoded> {
+    let table = Table::parse(elf)
+        .context("Failed to parse defmt table from ELF")?
+        .context("No defmt data found in ELF")?;
+
+    let locs = if locations {
+        table.get_locations(elf).ok()
+    } else {
+        None
+    };
+    let locs = locs.as_ref();
+
+    let mut decoder = table.new_stream_decoder();
+    decoder.received(stream);
+
+    let mut decoded = Decoded::default();
+    loop {
+        match decoder.decode() {
+            Ok(frame) => decoded.frames.push(format_frame(&frame, locs)),
+            Err(DecodeError::UnexpectedEof) => break,
+            Err(DecodeError::Malformed) => decoded.malformed += 1,
+        }
+    }
+    Ok(decoded)
+}
+
+fn format_frame(frame: &Frame, locs: Option<&Locations>) -> String {
+    let level = frame
+        .level()
+        .map(|l| l.as_str())
+        .unwrap_or("print")
+        .to_uppercase();
+
+    let mut line = match frame.display_timestamp() {
+        Some(timestamp) => format!("{timestamp} [{level:<5}] {}", frame.display_message()),
+        None => format!("[{level:<5}] {}", frame.display_message()),
+    };
+
+    if let Some(loc) = locs.and_then(|locs| locs.get(&frame.index())) {
+        line.push_str(&format!(
+            "\n└─ {} @ {}:{}",
+            loc.module,
+            loc.file.display(),
+            loc.line
+        ));
+    }
+    line
+}
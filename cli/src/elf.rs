@@ -0,0 +1,23 @@
+//! Lookup of the persist region in the firmware ELF file.
+
+use std::ops::Range;
+
+use anyhow::{Context, Result, bail};
+use object::{Object, ObjectSymbol};
+
+/// Returns the address range of the persist region from the linker symbols.
+pub fn persist_region(elf: &[u8]) -> Result<Range<u64>> {
+    let file = object::File::parse(elf).context("Failed to parse ELF file")?;
+    let symbol = |name: &str| {
+        file.symbols()
+            .find(|symbol| symbol.name() == Ok(name))
+            .map(|symbol| symbol.address())
+            .with_context(|| format!("Symbol `{name}` not found in ELF file"))
+    };
+
+    let region = symbol("__defmt_persist_start")?..symbol("__defmt_persist_end")?;
+    if region.is_empty() {
+        bail!("Persist region {region:#x?} is empty");
+    }
+    Ok(region)
+}
@@ -0,0 +1,4 @@
+//! Decoding of defmt-persist logs on the host, shared by the `defmt-persist` tool and the
+//! test runner.
+
+pub mod decode;
@@ -0,0 +1,132 @@
+//! Extracts defmt-persist logs from a debug probe or a RAM dump and decodes them.
+
+#[cfg(feature = "probe")]
+mod elf;
+#[cfg(feature = "probe")]
+mod probe;
+
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use defmt_persist::host;
+use defmt_persist_cli::decode;
+
+#[derive(Parser)]
+#[command(
+    name = "defmt-persist",
+    about = "Extract and decode defmt-persist logs from a debug probe or a RAM dump"
+)]
+struct Cli {
+    /// ELF file of the firmware that wrote the logs.
+    #[arg(long, short)]
+    elf: PathBuf,
+
+    /// The firmware was built with the `frame-crc` feature.
+    #[arg(long)]
+    frame_crc: bool,
+
+    /// The firmware was built with the `crash-ring` feature, with PERCENT of the region used
+    /// by the crash ring (25 unless `DEFMT_PERSIST_CRASH_PERCENT` was set).
+    #[arg(long, value_name = "PERCENT", value_parser = clap::value_parser!(u8).range(1..=90))]
+    crash_ring: Option<u8>,
+
+    #[command(subcommand)]
+    source: Source,
+}
+
+#[derive(Subcommand)]
+enum Source {
+    /// Read the persist region from a raw dump file.
+    File {
+        /// Dump of the whole persist region.
+        dump: PathBuf,
+    },
+
+    /// Read the persist region from a target over a debug probe.
+    ///
+    /// The region is located with the `__defmt_persist_start` and `__defmt_persist_end`
+    /// symbols in the ELF file. The target keeps running while the region is read.
+    #[cfg(feature = "probe")]
+    Probe {
+        /// Target chip, as named by probe-rs.
+        #[arg(long)]
+        chip: String,
+
+        /// Probe to use, as `VID:PID` or `VID:PID:Serial`. Defaults to the first one found.
+        #[arg(long)]
+        probe: Option<String>,
+    },
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    let elf = fs::read(&cli.elf).context("Failed to read ELF file")?;
+
+    let dump = match cli.source {
+        Source::File { dump } => fs::read(dump).context("Failed to read dump file")?,
+        #[cfg(feature = "probe")]
+        Source::Probe { chip, probe } => {
+            let region = elf::persist_region(&elf)?;
+            probe::read_region(&chip, probe.as_deref(), region)?
+        }
+    };
+
+    match cli.crash_ring {
+        Some(percent) => {
+            let (live, crash) = host::split_crash_ring(&dump, percent);
+            eprintln!("defmt-persist: live ring");
+            print_dump(&elf, live, cli.frame_crc)?;
+            eprintln!("defmt-persist: crash ring");
+            print_dump(&elf, crash, cli.frame_crc)
+        }
+        None => print_dump(&elf, &dump, cli.frame_crc),
+    }
+}
+
+/// Parses a dump of one ring buffer and prints its logs.
+fn print_dump(elf: &[u8], dump: &[u8], frame_crc: bool) -> Result<()> {
+    let parsed = host::parse(dump).context("Failed to parse persist region")?;
+    eprintln!(
+        "defmt-persist: {:?}, boot {}, reset reason: {:?}, firmware id: {:#010x}, {} frames dropped",
+        parsed.recovery_status,
+        parsed.boot_count,
+        parsed.reset_reason,
+        parsed.firmware_id,
+        parsed.dropped_frames,
+    );
+
+    let stream = if frame_crc {
+        let mut frames = parsed.verified_frames();
+        let mut stream = Vec::new();
+        for frame in &mut frames {
+            let (first, second) = frame.bufs();
+            stream.extend_from_slice(first);
+            stream.extend_from_slice(second);
+            stream.push(0);
+        }
+        if frames.discarded() != 0 {
+            eprintln!(
+                "defmt-persist: {} frames with a bad CRC skipped",
+                frames.discarded()
+            );
+        }
+        stream
+    } else {
+        parsed.to_vec()
+    };
+
+    let decoded = decode::decode(elf, &stream, true)?;
+    for frame in &decoded.frames {
+        println!("{frame}");
+    }
+    if decoded.malformed != 0 {
+        eprintln!(
+            "defmt-persist: {} malformed frames skipped",
+            decoded.malformed
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,35 @@
+//! Reading the persist region over a debug probe.
+
+use std::ops::Range;
+
+use anyhow::{Context, Result};
+use probe_rs::probe::{DebugProbeSelector, list::Lister};
+use probe_rs::{MemoryInterface, Permissions};
+
+/// Attaches to `chip` and reads the memory in `region`.
+pub fn read_region(chip: &str, selector: Option<&str>, region: Range<u64>) -> Result<Vec<u8>> {
+    let lister = Lister::new();
+    let probe = match selector {
+        Some(selector) => {
+            let selector: DebugProbeSelector =
+                selector.parse().context("Invalid probe selector")?;
+            lister.open(selector)
+        }
+        None => {
+            let probes = lister.list_all();
+            let info = probes.first().context("No debug probe found")?;
+            info.open()
+        }
+    }
+    .context("Failed to open debug probe")?;
+
+    let mut session = probe
+        .attach(chip, Permissions::default())
+        .context("Failed to attach to target")?;
+    let mut core = session.core(0).context("Failed to access core 0")?;
+
+    let mut dump = vec![0; (region.end - region.start) as usize];
+    core.read(region.start, &mut dump)
+        .context("Failed to read persist region")?;
+    Ok(dump)
+}
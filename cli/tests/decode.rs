@@ -0,0 +1,50 @@
+//! Decodes a persist region dump like the `defmt-persist file` command.
+//!
+//! `fixtures/firmware.elf` holds a defmt table with the formats `boot {=u32}` (println),
+//! `sensor ready` (info), `temperature {=i8} C` (warn) and `watchdog about to fire` (error).
+//! `fixtures/dump.bin` is a region without `ecc` holding one frame of each, wrapping around
+//! the end of its 32 byte buffer.
+
+use defmt_persist::{LogLevel, RecoveryStatus, ResetReason, host};
+use defmt_persist_cli::decode;
+
+const ELF: &[u8] = include_bytes!("fixtures/firmware.elf");
+const DUMP: &[u8] = include_bytes!("fixtures/dump.bin");
+
+#[test]
+fn decode_dump() {
+    let parsed = host::parse(DUMP).unwrap();
+    assert_eq!(parsed.recovery_status, RecoveryStatus::Valid);
+    assert!(!parsed.ecc);
+    assert_eq!(parsed.dropped_frames, 2);
+    assert_eq!(parsed.boot_count, 3);
+    assert_eq!(parsed.reset_reason, ResetReason::Watchdog);
+    assert_eq!(parsed.log_level, LogLevel::Info);
+    let (first, second) = parsed.bufs();
+    assert!(!first.is_empty() && !second.is_empty());
+
+    let decoded = decode::decode(ELF, &parsed.to_vec(), false).unwrap();
+    assert_eq!(decoded.malformed, 0);
+    assert_eq!(
+        decoded.frames,
+        [
+            "[PRINT] boot 3",
+            "[INFO ] sensor ready",
+            "[WARN ] temperature -5 C",
+            "[ERROR] watchdog about to fire",
+        ]
+    );
+}
+
+#[test]
+fn decode_corrupt_frame() {
+    let mut dump = DUMP.to_vec();
+    // The zero-run byte of the `boot` frame, at the end of the buffer.
+    let len = dump.len();
+    dump[len - 5] = 0x01;
+
+    let parsed = host::parse(&dump).unwrap();
+    let decoded = decode::decode(ELF, &parsed.to_vec(), false).unwrap();
+    assert_eq!(decoded.malformed, 1);
+    assert_eq!(decoded.frames[0], "[INFO ] sensor ready");
+}
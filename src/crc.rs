@@ -0,0 +1,39 @@
+//! CRC-32 (IEEE 802.3) used to validate persisted state.
+
+/// Reflected polynomial of CRC-32 (IEEE 802.3).
+const POLY: u32 = 0xedb8_8320;
+
+/// Initial value of the CRC register, pass it to the first [`crc32_update`].
+pub(crate) const CRC32_INIT: u32 = !0;
+
+/// Feeds `data` into the CRC register `crc`.
+///
+/// Bitwise implementation, as the checked data is small and a lookup table would cost 1 KiB
+/// of flash.
+pub(crate) const fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    let mut i = 0;
+    while i < data.len() {
+        crc ^= data[i] as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ POLY
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        i += 1;
+    }
+    crc
+}
+
+/// Turns the CRC register into the final CRC.
+pub(crate) const fn crc32_finish(crc: u32) -> u32 {
+    !crc
+}
+
+/// Computes the CRC-32 (IEEE 802.3) of `data`.
+pub(crate) const fn crc32(data: &[u8]) -> u32 {
+    crc32_finish(crc32_update(CRC32_INIT, data))
+}
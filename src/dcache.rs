@@ -0,0 +1,239 @@
+//! Cleaning of the Cortex-M7 data cache, so the persist region reaches RAM before a reset.
+//!
+//! Stores to a write-back cacheable region stay in the data cache until their line is evicted,
+//! and a reset discards them. With the `dcache` feature, the ring buffer cleans the lines it
+//! stored to by address after every write and every store to its header. This does nothing on
+//! cores without a data cache, or while the cache is disabled.
+
+use core::ops::Range;
+
+/// Size of a data cache line on Cortex-M7.
+#[cfg(target_arch = "arm")]
+const LINE: usize = 32;
+
+/// CPUID Base Register.
+#[cfg(target_arch = "arm")]
+const CPUID: usize = 0xe000_ed00;
+/// Part number of the Cortex-M7 in `CPUID`.
+#[cfg(target_arch = "arm")]
+const CPUID_CORTEX_M7: u32 = 0xc27;
+/// Configuration and Control Register.
+#[cfg(target_arch = "arm")]
+const CCR: usize = 0xe000_ed14;
+/// Data cache enable bit in `CCR`.
+#[cfg(target_arch = "arm")]
+const CCR_DC: u32 = 1 << 16;
+/// MPU Type, Control, Region Number, Region Base Address and Region Attribute and Size
+/// Registers.
+#[cfg(target_arch = "arm")]
+const MPU_TYPE: usize = 0xe000_ed90;
+#[cfg(target_arch = "arm")]
+const MPU_CTRL: usize = 0xe000_ed94;
+#[cfg(target_arch = "arm")]
+const MPU_RNR: usize = 0xe000_ed98;
+#[cfg(target_arch = "arm")]
+const MPU_RBAR: usize = 0xe000_ed9c;
+#[cfg(target_arch = "arm")]
+const MPU_RASR: usize = 0xe000_eda0;
+/// Data Cache Clean by Address to the Point of Coherency.
+#[cfg(target_arch = "arm")]
+const DCCMVAC: usize = 0xe000_ef68;
+/// Data TCM Control Register of the Cortex-M7.
+#[cfg(target_arch = "arm")]
+const DTCMCR: usize = 0xe000_ef94;
+/// Address of the data TCM on Cortex-M7.
+#[cfg(any(target_arch = "arm", test))]
+const DTCM_BASE: usize = 0x2000_0000;
+
+#[cfg(target_arch = "arm")]
+fn read(register: usize) -> u32 {
+    // SAFETY: The System Control Space is always mapped on Cortex-M, and the registers read
+    // here have no side effects on read.
+    unsafe { core::ptr::with_exposed_provenance::<u32>(register).read_volatile() }
+}
+
+#[cfg(target_arch = "arm")]
+fn write(register: usize, value: u32) {
+    // SAFETY: As in `read`. Callers only write registers that select or maintain state without
+    // changing memory contents.
+    unsafe { core::ptr::with_exposed_provenance_mut::<u32>(register).write_volatile(value) }
+}
+
+#[cfg(target_arch = "arm")]
+fn dsb() {
+    // SAFETY: A barrier has no other effect.
+    unsafe { core::arch::asm!("dsb sy", options(nostack, preserves_flags)) };
+}
+
+/// Cleans the data cache lines covering `range`, so its contents are written to RAM.
+#[cfg(target_arch = "arm")]
+pub(crate) fn clean(range: Range<usize>) {
+    if range.is_empty() || read(CCR) & CCR_DC == 0 {
+        return;
+    }
+    // Completes the stores to `range` before cleaning.
+    dsb();
+    let mut line = range.start & !(LINE - 1);
+    while line < range.end {
+        write(DCCMVAC, line as u32);
+        line += LINE;
+    }
+    dsb();
+}
+
+/// Cleans the data cache lines covering `range`. There is no data cache on this target.
+#[cfg(not(target_arch = "arm"))]
+pub(crate) fn clean(_range: Range<usize>) {}
+
+/// Returns whether stores to `range` may stay in the data cache, i.e. whether the range is
+/// write-back cacheable and the data cache is enabled.
+///
+/// On Cortex-M7, this takes the data TCM and the MPU regions into account. Other cores with a
+/// data cache are checked against the default memory map only.
+#[cfg(target_arch = "arm")]
+pub(crate) fn is_write_back(range: Range<usize>) -> bool {
+    if range.is_empty() || read(CCR) & CCR_DC == 0 {
+        return false;
+    }
+    let is_m7 = (read(CPUID) >> 4) & 0xfff == CPUID_CORTEX_M7;
+    [range.start, range.end - 1].into_iter().any(|addr| {
+        if !is_m7 {
+            return default_write_back(addr);
+        }
+        if tcm_contains(DTCM_BASE, read(DTCMCR), addr) {
+            return false;
+        }
+        mpu_write_back(addr).unwrap_or_else(|| default_write_back(addr))
+    })
+}
+
+/// Returns whether `range` may stay in the data cache. There is no data cache on this target.
+#[cfg(not(target_arch = "arm"))]
+pub(crate) fn is_write_back(_range: Range<usize>) -> bool {
+    false
+}
+
+/// Returns whether the enabled MPU makes `addr` write-back cacheable, or `None` if the MPU is
+/// disabled or no region contains `addr`.
+#[cfg(target_arch = "arm")]
+fn mpu_write_back(addr: usize) -> Option<bool> {
+    if read(MPU_CTRL) & 1 == 0 {
+        return None;
+    }
+    let regions = (read(MPU_TYPE) >> 8) & 0xff;
+    critical_section::with(|_| {
+        let selected = read(MPU_RNR);
+        // Higher regions take priority.
+        let attrs = (0..regions).rev().find_map(|region| {
+            write(MPU_RNR, region);
+            region_write_back(read(MPU_RBAR), read(MPU_RASR), addr)
+        });
+        write(MPU_RNR, selected);
+        attrs
+    })
+}
+
+/// Returns whether `addr` is write-back cacheable in the ARMv7-M default memory map.
+///
+/// The SRAM region and the first RAM region are write-back, the code region and the second
+/// RAM region are write-through, which does not lose stores on reset.
+#[cfg(any(target_arch = "arm", test))]
+fn default_write_back(addr: usize) -> bool {
+    (0x2000_0000..0x4000_0000).contains(&addr) || (0x6000_0000..0x8000_0000).contains(&addr)
+}
+
+/// Returns whether a TCM at `base`, described by its control register `cr`, contains `addr`.
+#[cfg(any(target_arch = "arm", test))]
+fn tcm_contains(base: usize, cr: u32, addr: usize) -> bool {
+    let size = (cr >> 3) & 0xf;
+    // Sizes start at 4 KiB for 0b0011.
+    cr & 1 != 0 && size != 0 && (base..base + (1024 << (size - 1))).contains(&addr)
+}
+
+/// Returns whether the MPU region described by `rbar` and `rasr` makes `addr` write-back
+/// cacheable, or `None` if it does not apply to `addr`.
+///
+/// Shareable memory is not cached by the Cortex-M7.
+#[cfg(any(target_arch = "arm", test))]
+fn region_write_back(rbar: u32, rasr: u32, addr: usize) -> Option<bool> {
+    if rasr & 1 == 0 {
+        return None;
+    }
+    let size = 1u64 << (((rasr >> 1) & 0x1f) + 1);
+    let base = u64::from(rbar) & !(size - 1);
+    let offset = (addr as u64).checked_sub(base).filter(|&o| o < size)?;
+    // Regions of 256 bytes and more have 8 subregions, which can be disabled.
+    if size >= 256 && rasr & (1 << (8 + offset / (size / 8))) != 0 {
+        return None;
+    }
+    let tex = (rasr >> 19) & 0b111;
+    let shareable = rasr & (1 << 18) != 0;
+    let cacheable = rasr & (1 << 17) != 0;
+    let bufferable = rasr & (1 << 16) != 0;
+    // Write-back is TEX 000 or 001 with C and B set, or TEX 1xx with an inner write-back
+    // policy, which is B set.
+    let write_back = bufferable && (tex & 0b100 != 0 || (tex <= 0b001 && cacheable));
+    Some(write_back && !shareable)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Builds an enabled RASR for a region of `2^(size + 1)` bytes.
+    fn rasr(size: u32, tex: u32, s: bool, c: bool, b: bool) -> u32 {
+        1 | size << 1 | tex << 19 | u32::from(s) << 18 | u32::from(c) << 17 | u32::from(b) << 16
+    }
+
+    #[test]
+    fn default_map() {
+        assert!(default_write_back(0x2400_0000));
+        assert!(default_write_back(0x6000_0000));
+        assert!(!default_write_back(0x0800_0000));
+        assert!(!default_write_back(0x4000_0000));
+        assert!(!default_write_back(0x9000_0000));
+    }
+
+    #[test]
+    fn tcm() {
+        // 128 KiB, enabled.
+        let cr = 0b1000 << 3 | 1;
+        assert!(tcm_contains(DTCM_BASE, cr, 0x2001_fffc));
+        assert!(!tcm_contains(DTCM_BASE, cr, 0x2002_0000));
+        assert!(!tcm_contains(DTCM_BASE, cr & !1, 0x2000_0000));
+    }
+
+    #[test]
+    fn mpu_region() {
+        // 64 KiB at 0x3000_0000.
+        let base = 0x3000_0000;
+        let wb = rasr(15, 0b001, false, true, true);
+        assert_eq!(region_write_back(base, wb, 0x3000_fffc), Some(true));
+        assert_eq!(region_write_back(base, wb, 0x3001_0000), None);
+        assert_eq!(region_write_back(base, wb & !1, 0x3000_0000), None);
+        // Subregion 1 disabled.
+        assert_eq!(region_write_back(base, wb | 1 << 9, 0x3000_2000), None);
+        assert_eq!(
+            region_write_back(base, wb | 1 << 9, 0x3000_4000),
+            Some(true)
+        );
+
+        let non_cacheable = rasr(15, 0b001, false, false, false);
+        assert_eq!(
+            region_write_back(base, non_cacheable, base as usize),
+            Some(false)
+        );
+        let write_through = rasr(15, 0b000, false, true, false);
+        assert_eq!(
+            region_write_back(base, write_through, base as usize),
+            Some(false)
+        );
+        let shareable = rasr(15, 0b001, true, true, true);
+        assert_eq!(
+            region_write_back(base, shareable, base as usize),
+            Some(false)
+        );
+        let inner_wb = rasr(15, 0b100, false, false, true);
+        assert_eq!(region_write_back(base, inner_wb, base as usize), Some(true));
+    }
+}
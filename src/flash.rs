@@ -0,0 +1,509 @@
+//! Copies of the persisted logs in NOR flash, enabled by the `flash` feature.
+//!
+//! The persist region survives resets, but not power loss. [`FlashLog::save`] copies the logs
+//! currently in the rings to a flash region, e.g. from the panic handler before a brown-out
+//! takes the RAM with it, and [`FlashLog::restore`] reads them back on the next boot.
+//!
+//! The copy starts with a small header, which is written last, so a save interrupted by a reset
+//! leaves no log behind rather than a partial one:
+//!
+//! | Offset | Field                                                    |
+//! |--------|----------------------------------------------------------|
+//! | 0      | Magic                                                    |
+//! | 4      | Length of the stored frames                              |
+//! | 8      | Boot counter of the run that saved the log               |
+//! | 12     | CRC-32 of the fields above and the stored frames         |
+//!
+//! The frames follow after the header, padded to the flash write size. They are stored without
+//! CRC trailers, so the restored bytes can be passed to a defmt decoder directly.
+
+use embedded_storage::nor_flash::NorFlash;
+
+#[cfg(feature = "frame-crc")]
+use crate::VerifiedFrames;
+use crate::crc::{CRC32_INIT, crc32_finish, crc32_update};
+use crate::frame::Frame;
+#[cfg(not(feature = "frame-crc"))]
+use crate::frame::Frames;
+
+pub use journal::{Journal, JournalBuffer, JournalReader, JournalSink};
+
+mod journal;
+
+/// Marks a saved log, "DPFL" in little endian.
+const MAGIC: u32 = 0x4c46_5044;
+
+/// Length of the header fields.
+const HEADER_LEN: usize = 16;
+
+/// Size of the buffer used to stage reads and writes, a multiple of the flash read and write
+/// sizes.
+const CHUNK: usize = 128;
+
+/// Error returned by [`FlashLog`] operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum FlashError<E> {
+    /// The flash driver returned an error.
+    Flash(E),
+    /// The region is not aligned to the erase size, or the read and write sizes of the flash
+    /// are not supported.
+    BadAlignment,
+    /// The region is too small to hold the header plus data.
+    TooSmall,
+    /// A saved log was found, but its CRC does not match.
+    Corrupt,
+}
+
+/// A flash region that holds a copy of the persisted logs.
+pub struct FlashLog<F> {
+    flash: F,
+    /// Start of the region.
+    offset: u32,
+    /// Size of the region, a multiple of the erase size.
+    size: u32,
+    /// Size of the header on flash, a multiple of the read and write sizes.
+    header_size: u32,
+}
+
+impl<F: NorFlash> FlashLog<F> {
+    /// Uses the `size` bytes at `offset` in `flash` to store the logs.
+    ///
+    /// The region must consist of whole erase sectors, and is erased by every save.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FlashError::BadAlignment`] if the region is not aligned to the erase size, or
+    /// the flash has read or write sizes that do not divide each other, the erase size, and
+    /// 128 bytes. Returns [`FlashError::TooSmall`] if the region cannot hold any data.
+    pub fn new(flash: F, offset: u32, size: u32) -> Result<Self, FlashError<F::Error>> {
+        let unit = check_region::<F>(offset, size)?;
+        let header_size = HEADER_LEN.next_multiple_of(unit as usize) as u32;
+        if size <= header_size {
+            return Err(FlashError::TooSmall);
+        }
+        Ok(FlashLog {
+            flash,
+            offset,
+            size,
+            header_size,
+        })
+    }
+
+    /// Returns the flash driver.
+    pub fn into_inner(self) -> F {
+        self.flash
+    }
+
+    /// Copies the logs in the rings to flash, replacing any log saved before.
+    ///
+    /// With the `crash-ring` feature, the crash ring is copied after the live ring. If the logs
+    /// do not fit, the oldest frames are left out. The logs stay in the rings.
+    ///
+    /// Interrupts are disabled until the copy is complete, which includes erasing the region.
+    /// This is meant for the panic or HardFault handler, or right before powering down.
+    ///
+    /// Returns the number of bytes stored, or `None` if the logger is not initialized.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FlashError::Flash`] if erasing or writing fails.
+    pub fn save(&mut self) -> Result<Option<usize>, FlashError<F::Error>> {
+        crate::logger::with_rings(|rings, boot_count| self.store(rings, boot_count)).transpose()
+    }
+
+    /// Erases the region, e.g. once a restored log has been forwarded.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FlashError::Flash`] if erasing fails.
+    pub fn erase(&mut self) -> Result<(), FlashError<F::Error>> {
+        self.flash
+            .erase(self.offset, self.offset + self.size)
+            .map_err(FlashError::Flash)
+    }
+
+    /// Returns a reader for the saved log, or `None` if there is none.
+    ///
+    /// The whole log is read once to verify its CRC.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FlashError::Corrupt`] if the CRC does not match, and [`FlashError::Flash`] if
+    /// reading fails.
+    pub fn restore(&mut self) -> Result<Option<FlashReader<'_, F>>, FlashError<F::Error>> {
+        let mut header = [0; CHUNK];
+        let header = &mut header[..self.header_size as usize];
+        self.flash
+            .read(self.offset, header)
+            .map_err(FlashError::Flash)?;
+
+        let field =
+            |i: usize| u32::from_le_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]);
+        let (len, boot_count, stored_crc) = (field(4), field(8), field(12));
+        if field(0) != MAGIC || len > self.size - self.header_size {
+            return Ok(None);
+        }
+
+        let mut reader = FlashReader {
+            flash: &mut self.flash,
+            pos: self.offset + self.header_size,
+            end: self.offset + self.header_size + len,
+            boot_count,
+        };
+        let mut crc = crc32_update(CRC32_INIT, &header[..12]);
+        let mut chunk = [0; CHUNK];
+        loop {
+            let n = reader.read(&mut chunk)?;
+            if n == 0 {
+                break;
+            }
+            crc = crc32_update(crc, &chunk[..n]);
+        }
+        if crc32_finish(crc) != stored_crc {
+            return Err(FlashError::Corrupt);
+        }
+
+        reader.pos = self.offset + self.header_size;
+        Ok(Some(reader))
+    }
+
+    /// Writes the frames in `rings` with a header to the region.
+    ///
+    /// Each ring is given as its two parts, like [`crate::GrantR::bufs`].
+    fn store(
+        &mut self,
+        rings: &[(&[u8], &[u8])],
+        boot_count: u32,
+    ) -> Result<usize, FlashError<F::Error>> {
+        let capacity = (self.size - self.header_size) as usize;
+
+        // Each frame is stored with its delimiter. Leave out the oldest frames until the rest fits.
+        let mut len: usize = frames(rings).map(|frame| frame.len() + 1).sum();
+        let mut skip = 0;
+        for frame in frames(rings) {
+            if len <= capacity {
+                break;
+            }
+            len -= frame.len() + 1;
+            skip += 1;
+        }
+
+        self.erase()?;
+
+        let mut crc = crc32_update(CRC32_INIT, &MAGIC.to_le_bytes());
+        crc = crc32_update(crc, &(len as u32).to_le_bytes());
+        crc = crc32_update(crc, &boot_count.to_le_bytes());
+
+        let mut writer = Writer {
+            flash: &mut self.flash,
+            pos: self.offset + self.header_size,
+            buf: [0; CHUNK],
+            len: 0,
+        };
+        for frame in frames(rings).skip(skip) {
+            let (first, second) = frame.bufs();
+            for bytes in [first, second, &[0]] {
+                crc = crc32_update(crc, bytes);
+                writer.push(bytes)?;
+            }
+        }
+        writer.finish()?;
+
+        // The header is written last, so an interrupted save leaves no valid log.
+        let mut header = [0; CHUNK];
+        header[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        header[4..8].copy_from_slice(&(len as u32).to_le_bytes());
+        header[8..12].copy_from_slice(&boot_count.to_le_bytes());
+        header[12..16].copy_from_slice(&crc32_finish(crc).to_le_bytes());
+        self.flash
+            .write(self.offset, &header[..self.header_size as usize])
+            .map_err(FlashError::Flash)?;
+        Ok(len)
+    }
+}
+
+/// Checks that the region at `offset` is made of whole erase sectors, and that the read and write
+/// sizes of the flash are supported.
+///
+/// Returns the larger of the read and write sizes, which is a multiple of both.
+fn check_region<F: NorFlash>(offset: u32, size: u32) -> Result<u32, FlashError<F::Error>> {
+    let unit = F::READ_SIZE.max(F::WRITE_SIZE);
+    let aligned = unit.is_multiple_of(F::READ_SIZE)
+        && unit.is_multiple_of(F::WRITE_SIZE)
+        && CHUNK.is_multiple_of(unit)
+        && F::ERASE_SIZE.is_multiple_of(unit)
+        && (offset as usize).is_multiple_of(F::ERASE_SIZE)
+        && (size as usize).is_multiple_of(F::ERASE_SIZE);
+    if !aligned {
+        return Err(FlashError::BadAlignment);
+    }
+    Ok(unit as u32)
+}
+
+/// Reads the bytes at `pos` into `buf`, through a chunk aligned to the read size.
+///
+/// Returns the number of bytes read, which is less than `buf.len()` if they would not fit in
+/// one chunk. The caller must make sure that the rest of the last read unit is in the flash.
+fn read_at<F: NorFlash>(
+    flash: &mut F,
+    pos: u32,
+    buf: &mut [u8],
+) -> Result<usize, FlashError<F::Error>> {
+    let skip = pos as usize % F::READ_SIZE;
+    let n = buf.len().min(CHUNK - skip);
+    if n == 0 {
+        return Ok(0);
+    }
+
+    let mut chunk = [0; CHUNK];
+    let chunk = &mut chunk[..(skip + n).next_multiple_of(F::READ_SIZE)];
+    flash
+        .read(pos - skip as u32, chunk)
+        .map_err(FlashError::Flash)?;
+    buf[..n].copy_from_slice(&chunk[skip..skip + n]);
+    Ok(n)
+}
+
+/// Iterates the complete frames in `rings`, without CRC trailers.
+fn frames<'a>(rings: &[(&'a [u8], &'a [u8])]) -> impl Iterator<Item = Frame<'a>> {
+    rings.iter().flat_map(|&(first, second)| {
+        #[cfg(feature = "frame-crc")]
+        let frames = VerifiedFrames::new(first, second);
+        #[cfg(not(feature = "frame-crc"))]
+        let frames = Frames::new(first, second);
+        frames
+    })
+}
+
+/// Stages bytes in chunks of whole flash words.
+struct Writer<'a, F> {
+    flash: &'a mut F,
+    /// Flash offset of `buf`.
+    pos: u32,
+    buf: [u8; CHUNK],
+    len: usize,
+}
+
+impl<F: NorFlash> Writer<'_, F> {
+    fn push(&mut self, mut bytes: &[u8]) -> Result<(), FlashError<F::Error>> {
+        while !bytes.is_empty() {
+            let n = bytes.len().min(CHUNK - self.len);
+            self.buf[self.len..self.len + n].copy_from_slice(&bytes[..n]);
+            self.len += n;
+            bytes = &bytes[n..];
+            if self.len == CHUNK {
+                self.finish()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the staged bytes, padded with zeros to the write size.
+    fn finish(&mut self) -> Result<(), FlashError<F::Error>> {
+        let len = self.len.next_multiple_of(F::WRITE_SIZE);
+        self.buf[self.len..len].fill(0);
+        self.flash
+            .write(self.pos, &self.buf[..len])
+            .map_err(FlashError::Flash)?;
+        self.pos += len as u32;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+/// Reads a log saved in flash, returned by [`FlashLog::restore`].
+///
+/// The stream holds complete frames, and can be passed to a defmt decoder as it is.
+pub struct FlashReader<'a, F> {
+    flash: &'a mut F,
+    /// Flash offset of the next byte to read.
+    pos: u32,
+    /// Flash offset just past the log.
+    end: u32,
+    boot_count: u32,
+}
+
+impl<F: NorFlash> FlashReader<'_, F> {
+    /// Boot counter of the run that saved the log, see `ConsumerAndMetadata::boot_count`.
+    #[inline]
+    pub fn boot_count(&self) -> u32 {
+        self.boot_count
+    }
+
+    /// Returns the number of bytes left to read.
+    #[inline]
+    pub fn remaining(&self) -> usize {
+        (self.end - self.pos) as usize
+    }
+
+    /// Reads the next bytes of the log into `buf`.
+    ///
+    /// Returns the number of bytes read, which is 0 at the end of the log.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FlashError::Flash`] if reading fails.
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, FlashError<F::Error>> {
+        // The log starts aligned, and the region is padded past its end.
+        let len = buf.len().min(self.remaining());
+        let n = read_at(self.flash, self.pos, &mut buf[..len])?;
+        self.pos += n as u32;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use embedded_storage::nor_flash::{
+        ErrorType, NorFlashErrorKind, ReadNorFlash, check_erase, check_read, check_write,
+    };
+
+    /// NOR flash emulated in RAM, with 256-byte sectors and 4-byte words.
+    pub(super) struct RamFlash(pub(super) [u8; 1024]);
+
+    impl ErrorType for RamFlash {
+        type Error = NorFlashErrorKind;
+    }
+
+    impl ReadNorFlash for RamFlash {
+        const READ_SIZE: usize = 1;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            check_read(self, offset, bytes.len())?;
+            let offset = offset as usize;
+            bytes.copy_from_slice(&self.0[offset..offset + bytes.len()]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.0.len()
+        }
+    }
+
+    impl NorFlash for RamFlash {
+        const WRITE_SIZE: usize = 4;
+        const ERASE_SIZE: usize = 256;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            check_erase(self, from, to)?;
+            self.0[from as usize..to as usize].fill(0xff);
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            check_write(self, offset, bytes.len())?;
+            let offset = offset as usize;
+            // NOR flash can only clear bits.
+            for (dst, src) in self.0[offset..].iter_mut().zip(bytes) {
+                *dst &= src;
+            }
+            Ok(())
+        }
+    }
+
+    /// Appends `body` to `buf` as the logger stores it.
+    fn push_frame(buf: &mut [u8; 1024], len: &mut usize, body: &[u8]) {
+        buf[*len..*len + body.len()].copy_from_slice(body);
+        *len += body.len();
+        #[cfg(feature = "frame-crc")]
+        {
+            let trailer = crate::frame::trailer(crate::crc::crc32(body));
+            buf[*len..*len + trailer.len()].copy_from_slice(&trailer);
+            *len += trailer.len();
+        }
+        buf[*len] = 0;
+        *len += 1;
+    }
+
+    /// Reads the whole saved log in small pieces.
+    fn restore_all(
+        log: &mut FlashLog<&mut RamFlash>,
+        restored: &mut [u8; 1024],
+    ) -> Option<(u32, usize)> {
+        let mut reader = log.restore().unwrap()?;
+        let mut len = 0;
+        loop {
+            let n = reader
+                .read(&mut restored[len..(len + 7).min(1024)])
+                .unwrap();
+            if n == 0 {
+                break;
+            }
+            len += n;
+        }
+        Some((reader.boot_count(), len))
+    }
+
+    #[test]
+    fn save_and_restore() {
+        let mut flash = RamFlash([0xff; 1024]);
+        let mut log = FlashLog::new(&mut flash, 256, 768).unwrap();
+        let mut restored = [0; 1024];
+        assert_eq!(restore_all(&mut log, &mut restored), None);
+
+        let (mut live, mut live_len) = ([0; 1024], 0);
+        push_frame(&mut live, &mut live_len, &[1, 1]);
+        push_frame(&mut live, &mut live_len, &[2; 200]);
+        let (mut crash, mut crash_len) = ([0; 1024], 0);
+        push_frame(&mut crash, &mut crash_len, &[3; 300]);
+        let (first, second) = live[..live_len].split_at(3);
+        let rings = [(first, second), (&crash[..crash_len], &[][..])];
+        assert_eq!(log.store(&rings, 7), Ok(505));
+
+        assert_eq!(restore_all(&mut log, &mut restored), Some((7, 505)));
+        assert_eq!(restored[..3], [1, 1, 0]);
+        assert_eq!(restored[3..203], [2; 200]);
+        assert_eq!(restored[203], 0);
+        assert_eq!(restored[204..504], [3; 300]);
+        assert_eq!(restored[504], 0);
+
+        // An erased region holds no log.
+        log.erase().unwrap();
+        assert_eq!(restore_all(&mut log, &mut restored), None);
+    }
+
+    #[test]
+    fn keep_newest() {
+        let mut flash = RamFlash([0xff; 1024]);
+        let mut log = FlashLog::new(&mut flash, 0, 256).unwrap();
+        let (mut live, mut len) = ([0; 1024], 0);
+        push_frame(&mut live, &mut len, &[1; 100]);
+        push_frame(&mut live, &mut len, &[2; 100]);
+        push_frame(&mut live, &mut len, &[3; 100]);
+        assert_eq!(log.store(&[(&live[..len], &[])], 0), Ok(202));
+
+        let mut restored = [0; 1024];
+        assert_eq!(restore_all(&mut log, &mut restored), Some((0, 202)));
+        assert_eq!(restored[..100], [2; 100]);
+        assert_eq!(restored[101..201], [3; 100]);
+    }
+
+    #[test]
+    fn corrupt() {
+        let mut flash = RamFlash([0xff; 1024]);
+        let (mut live, mut len) = ([0; 1024], 0);
+        push_frame(&mut live, &mut len, &[1; 10]);
+        FlashLog::new(&mut flash, 0, 256)
+            .unwrap()
+            .store(&[(&live[..len], &[])], 0)
+            .unwrap();
+
+        flash.0[HEADER_LEN + 3] ^= 1;
+        let mut log = FlashLog::new(&mut flash, 0, 256).unwrap();
+        assert_eq!(log.restore().err(), Some(FlashError::Corrupt));
+    }
+
+    #[test]
+    fn bad_region() {
+        let mut flash = RamFlash([0xff; 1024]);
+        assert_eq!(
+            FlashLog::new(&mut flash, 128, 256).err(),
+            Some(FlashError::BadAlignment)
+        );
+        assert_eq!(
+            FlashLog::new(&mut flash, 0, 0).err(),
+            Some(FlashError::TooSmall)
+        );
+    }
+}
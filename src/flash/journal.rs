@@ -0,0 +1,889 @@
+//! An append-only journal of defmt frames in NOR flash.
+//!
+//! The journal uses its sectors round-robin, so they wear evenly. Each sector starts with a
+//! header holding a sequence number, which increases with every sector opened, followed by
+//! records:
+//!
+//! | Offset | Field                                     |
+//! |--------|-------------------------------------------|
+//! | 0      | Length of the data                        |
+//! | 4      | CRC-32 of the data                        |
+//! | 8      | Data, padded to the flash read and write sizes |
+//!
+//! A record header is written after its data, so a record cut short by a reset is never mistaken
+//! for a complete one. [`Journal::init`] finds the newest sector by its sequence number, and
+//! continues after its last complete record, or in the next sector if the rest of the newest
+//! sector is not erased. When all sectors are full, the oldest one is erased to make room.
+//!
+//! Registered as a sink through a [`JournalSink`], the journal stores every frame the logger
+//! writes as a record of its own. A [`JournalReader`] reads the records back through a
+//! [`Consumer`], like the logs in the ring buffer.
+
+use core::cell::{RefCell, UnsafeCell};
+use core::mem::MaybeUninit;
+
+use critical_section::Mutex;
+use embedded_storage::nor_flash::NorFlash;
+
+use super::{CHUNK, FlashError, check_region, read_at};
+use crate::Consumer;
+use crate::crc::{CRC32_INIT, crc32_finish, crc32_update};
+use crate::ring_buffer::{Producer, RingBuffer};
+use crate::sink::Sink;
+
+/// Marks a journal sector, "DPJL" in little endian.
+const MAGIC: u32 = 0x4c4a_5044;
+
+/// Length of the sector header fields: magic, sequence number and their CRC-32.
+const SECTOR_HEADER_LEN: usize = 12;
+
+/// Length of the record header fields: data length and CRC-32.
+const RECORD_HEADER_LEN: usize = 8;
+
+/// What the journal holds at a record position.
+enum Slot {
+    /// Erased, no record was started here.
+    Free,
+    /// A complete record with this many bytes of data.
+    Record(u32),
+    /// A record that was cut short or corrupted.
+    Corrupt,
+}
+
+/// The record being written, see [`Journal::write`].
+#[derive(Clone, Copy)]
+struct Record {
+    /// Position of the record in the head sector.
+    start: u32,
+    len: u32,
+    /// CRC register over the data.
+    crc: u32,
+}
+
+/// An append-only log of defmt frames spread over several flash sectors.
+///
+/// Frames are appended by the logger through a [`JournalSink`], or with [`Journal::write`] and
+/// [`Journal::commit`]. They are read back through a [`JournalReader`].
+pub struct Journal<F> {
+    flash: F,
+    /// Start of the region.
+    offset: u32,
+    /// Number of sectors in the region.
+    sectors: u32,
+    /// Larger of the flash read and write sizes.
+    unit: u32,
+    /// Size of the sector header on flash.
+    sector_header: u32,
+    /// Size of the record header on flash.
+    record_header: u32,
+    /// Sector being appended to.
+    head: u32,
+    /// Sequence number of `head`.
+    seq: u32,
+    /// Position of the next record in `head`.
+    write: u32,
+    record: Option<Record>,
+    /// Data of `record` not written to flash yet.
+    buf: [u8; CHUNK],
+    staged: usize,
+    /// Oldest sector that holds records.
+    tail: u32,
+    /// Sector being read.
+    read_sector: u32,
+    /// Position of the next byte to read in `read_sector`.
+    read_pos: u32,
+    /// End of the data of the record being read.
+    read_end: u32,
+    /// Position of the next record to read.
+    read_next: u32,
+}
+
+impl<F: NorFlash> Journal<F> {
+    /// Opens the journal in the `size` bytes at `offset` in `flash`, recovering its records.
+    ///
+    /// The region must consist of at least two erase sectors. A new region does not need to be
+    /// erased first, sectors without a valid header are erased when they are used.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FlashError::BadAlignment`] if the region is not aligned to the erase size, or
+    /// the flash has read or write sizes that do not divide each other, the erase size, and
+    /// 128 bytes. Returns [`FlashError::TooSmall`] if the region has fewer than two sectors, or
+    /// the sectors cannot hold any data. Returns [`FlashError::Flash`] if reading fails.
+    pub fn init(flash: F, offset: u32, size: u32) -> Result<Self, FlashError<F::Error>> {
+        let unit = check_region::<F>(offset, size)?;
+        let sectors = size / F::ERASE_SIZE as u32;
+        let sector_header = SECTOR_HEADER_LEN.next_multiple_of(unit as usize) as u32;
+        let record_header = RECORD_HEADER_LEN.next_multiple_of(unit as usize) as u32;
+        if sectors < 2 || sector_header + record_header + unit > F::ERASE_SIZE as u32 {
+            return Err(FlashError::TooSmall);
+        }
+
+        // Without any valid sector, start as if the last one was full, so the first write
+        // opens sector 0 with sequence number 0.
+        let mut journal = Journal {
+            flash,
+            offset,
+            sectors,
+            unit,
+            sector_header,
+            record_header,
+            head: sectors - 1,
+            seq: u32::MAX,
+            write: F::ERASE_SIZE as u32,
+            record: None,
+            buf: [0; CHUNK],
+            staged: 0,
+            tail: sectors - 1,
+            read_sector: sectors - 1,
+            read_pos: 0,
+            read_end: 0,
+            read_next: sector_header,
+        };
+
+        // Sequence numbers wrap, the sectors in use are a few numbers apart.
+        let mut newest = None;
+        for sector in 0..sectors {
+            if let Some(seq) = journal.sector_seq(sector)?
+                && newest.is_none_or(|(_, newest)| seq.wrapping_sub(newest) as i32 > 0)
+            {
+                newest = Some((sector, seq));
+            }
+        }
+        let Some((head, seq)) = newest else {
+            return Ok(journal);
+        };
+        journal.head = head;
+        journal.seq = seq;
+        journal.write = journal.recover_write()?;
+
+        // The sectors before the head hold older records as long as their sequence numbers
+        // count down.
+        journal.tail = head;
+        for back in 1..sectors {
+            let sector = (head + sectors - back) % sectors;
+            if journal.sector_seq(sector)? != Some(seq.wrapping_sub(back)) {
+                break;
+            }
+            journal.tail = sector;
+        }
+        journal.read_sector = journal.tail;
+        Ok(journal)
+    }
+
+    /// Returns the flash driver.
+    pub fn into_inner(self) -> F {
+        self.flash
+    }
+
+    /// Appends `bytes` to the current record.
+    ///
+    /// The record is completed by [`Journal::commit`], or earlier if the sector fills up. A
+    /// record that is not completed before a reset is lost.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FlashError::Flash`] if erasing or writing fails. The current record is lost,
+    /// and the next one starts in a new sector.
+    pub fn write(&mut self, bytes: &[u8]) -> Result<(), FlashError<F::Error>> {
+        let result = self.write_record(bytes);
+        if result.is_err() {
+            self.abandon();
+        }
+        result
+    }
+
+    /// Completes the current record, making it visible to [`JournalReader::fill`] and to
+    /// [`Journal::init`] after a reset.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FlashError::Flash`] if writing fails. The current record is lost, and the next
+    /// one starts in a new sector.
+    pub fn commit(&mut self) -> Result<(), FlashError<F::Error>> {
+        let result = self.commit_record();
+        if result.is_err() {
+            self.abandon();
+        }
+        result
+    }
+
+    /// Erases the sectors that have been read completely, so their records are not read again
+    /// after a reset.
+    ///
+    /// The records read from the sector that is being read or appended to are kept until the
+    /// reading moves past it.
+    fn release(&mut self) -> Result<(), FlashError<F::Error>> {
+        while self.tail != self.read_sector && self.tail != self.head {
+            self.erase(self.tail)?;
+            self.tail = (self.tail + 1) % self.sectors;
+        }
+        Ok(())
+    }
+
+    /// Returns the flash offset of `pos` in `sector`.
+    fn addr(&self, sector: u32, pos: u32) -> u32 {
+        self.offset + sector * F::ERASE_SIZE as u32 + pos
+    }
+
+    fn erase(&mut self, sector: u32) -> Result<(), FlashError<F::Error>> {
+        let from = self.addr(sector, 0);
+        self.flash
+            .erase(from, from + F::ERASE_SIZE as u32)
+            .map_err(FlashError::Flash)
+    }
+
+    /// Returns the sequence number of `sector`, or `None` if its header is not valid.
+    fn sector_seq(&mut self, sector: u32) -> Result<Option<u32>, FlashError<F::Error>> {
+        let mut header = [0; SECTOR_HEADER_LEN];
+        let pos = self.addr(sector, 0);
+        let n = read_at(&mut self.flash, pos, &mut header)?;
+        debug_assert_eq!(n, SECTOR_HEADER_LEN);
+
+        let field =
+            |i: usize| u32::from_le_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]);
+        let valid =
+            field(0) == MAGIC && field(8) == crc32_finish(crc32_update(CRC32_INIT, &header[..8]));
+        Ok(valid.then(|| field(4)))
+    }
+
+    /// Checks the record at `pos` in `sector`, verifying the CRC of its data.
+    fn slot(&mut self, sector: u32, pos: u32) -> Result<Slot, FlashError<F::Error>> {
+        let sector_size = F::ERASE_SIZE as u32;
+        if pos + self.record_header > sector_size {
+            return Ok(Slot::Free);
+        }
+
+        let mut header = [0; RECORD_HEADER_LEN];
+        let addr = self.addr(sector, pos);
+        let n = read_at(&mut self.flash, addr, &mut header)?;
+        debug_assert_eq!(n, RECORD_HEADER_LEN);
+        if header == [0xff; RECORD_HEADER_LEN] {
+            return Ok(Slot::Free);
+        }
+
+        let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let stored_crc = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        let data = pos + self.record_header;
+        if len == 0 || len > sector_size - data {
+            return Ok(Slot::Corrupt);
+        }
+
+        let mut crc = CRC32_INIT;
+        let mut chunk = [0; CHUNK];
+        let mut done = 0;
+        while done < len {
+            let want = (len - done).min(CHUNK as u32) as usize;
+            let pos = self.addr(sector, data + done);
+            let n = read_at(&mut self.flash, pos, &mut chunk[..want])?;
+            crc = crc32_update(crc, &chunk[..n]);
+            done += n as u32;
+        }
+        Ok(if crc32_finish(crc) == stored_crc {
+            Slot::Record(len)
+        } else {
+            Slot::Corrupt
+        })
+    }
+
+    /// Returns the position in `sector` just past the record with `len` bytes of data at `pos`.
+    fn record_end(&self, pos: u32, len: u32) -> u32 {
+        pos + self.record_header + len.next_multiple_of(self.unit)
+    }
+
+    /// Finds the position after the last complete record in the head sector.
+    ///
+    /// Returns the sector size if the rest of the sector cannot be written, e.g. because a
+    /// record was cut short by a reset.
+    fn recover_write(&mut self) -> Result<u32, FlashError<F::Error>> {
+        let sector_size = F::ERASE_SIZE as u32;
+        let mut pos = self.sector_header;
+        loop {
+            match self.slot(self.head, pos)? {
+                Slot::Record(len) => pos = self.record_end(pos, len),
+                Slot::Free => {
+                    // The data of an interrupted record is written before its header.
+                    let mut chunk = [0; CHUNK];
+                    let mut blank = pos;
+                    while blank < sector_size {
+                        let want = ((sector_size - blank) as usize).min(CHUNK);
+                        let addr = self.addr(self.head, blank);
+                        let n = read_at(&mut self.flash, addr, &mut chunk[..want])?;
+                        if chunk[..n].iter().any(|&b| b != 0xff) {
+                            return Ok(sector_size);
+                        }
+                        blank += n as u32;
+                    }
+                    return Ok(pos);
+                }
+                Slot::Corrupt => return Ok(sector_size),
+            }
+        }
+    }
+
+    /// Erases the sector after the head and makes it the new head.
+    ///
+    /// If that sector holds the oldest records, they are lost.
+    fn open_sector(&mut self) -> Result<(), FlashError<F::Error>> {
+        let next = (self.head + 1) % self.sectors;
+        if next == self.tail && next != self.head {
+            self.tail = (next + 1) % self.sectors;
+            if self.read_sector == next {
+                self.read_sector = self.tail;
+                self.read_pos = 0;
+                self.read_end = 0;
+                self.read_next = self.sector_header;
+            }
+        }
+
+        self.erase(next)?;
+        let seq = self.seq.wrapping_add(1);
+        let mut header = [0xff; CHUNK];
+        header[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        header[4..8].copy_from_slice(&seq.to_le_bytes());
+        let crc = crc32_finish(crc32_update(CRC32_INIT, &header[..8]));
+        header[8..12].copy_from_slice(&crc.to_le_bytes());
+        let addr = self.addr(next, 0);
+        self.flash
+            .write(addr, &header[..self.sector_header as usize])
+            .map_err(FlashError::Flash)?;
+
+        self.head = next;
+        self.seq = seq;
+        self.write = self.sector_header;
+        Ok(())
+    }
+
+    fn write_record(&mut self, mut bytes: &[u8]) -> Result<(), FlashError<F::Error>> {
+        while !bytes.is_empty() {
+            let mut record = match self.record {
+                Some(record) => record,
+                None => self.begin_record()?,
+            };
+            let capacity = F::ERASE_SIZE as u32 - record.start - self.record_header;
+            let n = bytes
+                .len()
+                .min((capacity - record.len) as usize)
+                .min(CHUNK - self.staged);
+            self.buf[self.staged..self.staged + n].copy_from_slice(&bytes[..n]);
+            self.staged += n;
+            record.len += n as u32;
+            record.crc = crc32_update(record.crc, &bytes[..n]);
+            self.record = Some(record);
+            bytes = &bytes[n..];
+
+            if self.staged == CHUNK {
+                self.flush(record)?;
+            }
+            if record.len == capacity {
+                self.commit_record()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts a record at the write position, opening a new sector if there is no room left.
+    fn begin_record(&mut self) -> Result<Record, FlashError<F::Error>> {
+        if self.write + self.record_header + self.unit > F::ERASE_SIZE as u32 {
+            self.open_sector()?;
+        }
+        Ok(Record {
+            start: self.write,
+            len: 0,
+            crc: CRC32_INIT,
+        })
+    }
+
+    /// Writes the staged data of `record`, padded with zeros to the write size.
+    fn flush(&mut self, record: Record) -> Result<(), FlashError<F::Error>> {
+        let written = record.len - self.staged as u32;
+        let addr = self.addr(self.head, record.start + self.record_header + written);
+        let len = self.staged.next_multiple_of(F::WRITE_SIZE);
+        self.buf[self.staged..len].fill(0);
+        self.flash
+            .write(addr, &self.buf[..len])
+            .map_err(FlashError::Flash)?;
+        self.staged = 0;
+        Ok(())
+    }
+
+    fn commit_record(&mut self) -> Result<(), FlashError<F::Error>> {
+        let Some(record) = self.record else {
+            return Ok(());
+        };
+        if self.staged != 0 {
+            self.flush(record)?;
+        }
+
+        let mut header = [0xff; CHUNK];
+        header[0..4].copy_from_slice(&record.len.to_le_bytes());
+        header[4..8].copy_from_slice(&crc32_finish(record.crc).to_le_bytes());
+        let addr = self.addr(self.head, record.start);
+        self.flash
+            .write(addr, &header[..self.record_header as usize])
+            .map_err(FlashError::Flash)?;
+
+        self.record = None;
+        self.write = self.record_end(record.start, record.len);
+        Ok(())
+    }
+
+    /// Drops the current record after a failed write. The next record starts in a new sector,
+    /// as the state of the rest of this one is unknown.
+    fn abandon(&mut self) {
+        self.record = None;
+        self.staged = 0;
+        self.write = F::ERASE_SIZE as u32;
+    }
+
+    /// Moves the reader to the next complete record.
+    ///
+    /// Returns `false` if all committed records have been read.
+    fn next_record(&mut self) -> Result<bool, FlashError<F::Error>> {
+        loop {
+            if self.read_sector == self.head && self.read_next >= self.write {
+                return Ok(false);
+            }
+            match self.slot(self.read_sector, self.read_next)? {
+                Slot::Record(len) => {
+                    self.read_pos = self.read_next + self.record_header;
+                    self.read_end = self.read_pos + len;
+                    self.read_next = self.record_end(self.read_next, len);
+                    return Ok(true);
+                }
+                // A corrupt length cannot be skipped, so the rest of the sector is lost.
+                Slot::Free | Slot::Corrupt if self.read_sector == self.head => return Ok(false),
+                Slot::Free | Slot::Corrupt => {
+                    // Sectors with a damaged header are skipped.
+                    loop {
+                        self.read_sector = (self.read_sector + 1) % self.sectors;
+                        if self.read_sector == self.head
+                            || self.sector_seq(self.read_sector)?.is_some()
+                        {
+                            break;
+                        }
+                    }
+                    self.read_next = self.sector_header;
+                }
+            }
+        }
+    }
+}
+
+/// RAM that stages the records read from a [`Journal`], see [`JournalReader`].
+///
+/// With the `word-write` feature, `N` must be a multiple of the word size.
+#[repr(C)]
+pub struct JournalBuffer<const N: usize> {
+    ring: RingBuffer,
+    buf: [UnsafeCell<MaybeUninit<u8>>; N],
+}
+
+impl<const N: usize> JournalBuffer<N> {
+    /// Creates an empty buffer.
+    pub fn new() -> Self {
+        Self {
+            ring: RingBuffer::new(0, 0),
+            buf: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+        }
+    }
+}
+
+impl<const N: usize> Default for JournalBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reads the records of a [`Journal`] through a [`Consumer`].
+///
+/// [`JournalReader::fill`] copies the oldest records that were not read yet into a
+/// [`JournalBuffer`]. They are then read like the ring buffer, with a [`GrantR`](crate::GrantR) from
+/// [`JournalReader::consumer`] and its frames, and released once they were forwarded. The
+/// sectors they were copied from are erased by the next `fill` after all of them were released,
+/// so records that were not released are read again after a reset.
+///
+/// The journal checks a CRC per record, so the frames are staged without CRC trailers. Iterate
+/// them with [`GrantR::frames`](crate::GrantR::frames), also with the `frame-crc` feature. A record that is larger than
+/// the buffer is dropped and counted in [`Consumer::dropped_frames`].
+///
+/// ```ignore
+/// use defmt_persist::flash::{JournalBuffer, JournalReader};
+///
+/// let mut buffer = JournalBuffer::<256>::new();
+/// let mut reader = JournalReader::new(&mut buffer);
+/// while matches!(JOURNAL.with(|journal| reader.fill(journal)), Some(Ok(true))) {
+///     let grant = reader.consumer().read();
+///     let (first, second) = grant.bufs();
+///     transmit(first);
+///     transmit(second);
+///     grant.release_all();
+/// }
+/// ```
+pub struct JournalReader<'a> {
+    producer: Producer<'a>,
+    consumer: Consumer<'a>,
+}
+
+impl<'a> JournalReader<'a> {
+    /// Creates a reader that stages the records in `buffer`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `N` is not less than `i32::MAX / 4`, or with the `word-write` feature, if it is
+    /// not a multiple of the word size.
+    pub fn new<const N: usize>(buffer: &'a mut JournalBuffer<N>) -> Self {
+        assert!(N < i32::MAX as usize / 4, "the journal buffer is too large");
+        #[cfg(feature = "word-write")]
+        {
+            let word = size_of::<crate::ring_buffer::Word>();
+            assert!(
+                N.is_multiple_of(word) && buffer.buf.as_ptr().addr().is_multiple_of(word),
+                "the journal buffer must hold whole words"
+            );
+        }
+        let JournalBuffer { ring, buf } = buffer;
+        // SAFETY: `buf.len()` is checked above.
+        let (producer, consumer) = unsafe { ring.split(buf) };
+        Self { producer, consumer }
+    }
+
+    /// Returns the consumer of the staged records.
+    pub fn consumer(&mut self) -> &mut Consumer<'a> {
+        &mut self.consumer
+    }
+
+    /// Copies the next records of `journal` that fit into the buffer, oldest first.
+    ///
+    /// If everything staged before was released, the sectors it was read from are erased first.
+    ///
+    /// Returns whether there is anything to read from [`JournalReader::consumer`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FlashError::Flash`] if reading or erasing fails. The record being copied is
+    /// read again by the next call.
+    pub fn fill<F: NorFlash>(
+        &mut self,
+        journal: &mut Journal<F>,
+    ) -> Result<bool, FlashError<F::Error>> {
+        if self.consumer.is_empty() {
+            journal.release()?;
+        }
+        loop {
+            if journal.read_pos == journal.read_end && !journal.next_record()? {
+                break;
+            }
+            // A record that can never fit is dropped by the producer.
+            let len = journal.read_end - journal.read_pos;
+            if !self.producer.fits(len as usize) && !self.consumer.is_empty() {
+                break;
+            }
+            let start = journal.read_pos;
+            if let Err(e) = self.stage(journal) {
+                self.producer.abort();
+                journal.read_pos = start;
+                return Err(e);
+            }
+            self.producer.commit();
+        }
+        Ok(!self.consumer.is_empty())
+    }
+
+    /// Writes the rest of the record being read from `journal` to the producer.
+    fn stage<F: NorFlash>(&mut self, journal: &mut Journal<F>) -> Result<(), FlashError<F::Error>> {
+        let mut chunk = [0; CHUNK];
+        while journal.read_pos < journal.read_end {
+            // The record data starts aligned, and is padded past its end.
+            let want = ((journal.read_end - journal.read_pos) as usize).min(CHUNK);
+            let pos = journal.addr(journal.read_sector, journal.read_pos);
+            let n = read_at(&mut journal.flash, pos, &mut chunk[..want])?;
+            self.producer.write(&chunk[..n]);
+            journal.read_pos += n as u32;
+        }
+        Ok(())
+    }
+}
+
+/// A [`Sink`] that stores everything logged in a [`Journal`], as it is written.
+///
+/// Each frame is committed as a record once its final zero byte is written, which ends every
+/// frame in the rzcobs encoding. A frame that fails to be written is dropped, like a frame that
+/// does not fit in the ring. The flash is written inside the logger's critical section, so
+/// logging takes as long as programming the flash, and erasing a sector when one fills up.
+///
+/// ```ignore
+/// use defmt_persist::flash::{Journal, JournalSink};
+///
+/// static JOURNAL: JournalSink<Flash> = JournalSink::new();
+///
+/// JOURNAL.attach(Journal::init(flash, JOURNAL_OFFSET, JOURNAL_SIZE).unwrap());
+/// defmt_persist::add_sink(&JOURNAL).unwrap();
+/// ```
+pub struct JournalSink<F> {
+    state: Mutex<RefCell<SinkState<F>>>,
+}
+
+struct SinkState<F> {
+    journal: Option<Journal<F>>,
+    /// A write of the current frame failed, so the rest of it is dropped.
+    failed: bool,
+}
+
+impl<F> JournalSink<F> {
+    /// Creates a sink without a journal, which drops everything written to it.
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::new(RefCell::new(SinkState {
+                journal: None,
+                failed: false,
+            })),
+        }
+    }
+
+    /// Starts storing the logs in `journal`, and returns the journal used before, if any.
+    ///
+    /// The first frame stored may be cut, if `journal` is attached while a frame is logged.
+    pub fn attach(&self, journal: Journal<F>) -> Option<Journal<F>> {
+        critical_section::with(|cs| {
+            let mut state = self.state.borrow_ref_mut(cs);
+            state.failed = false;
+            state.journal.replace(journal)
+        })
+    }
+
+    /// Stops storing the logs and returns the journal.
+    ///
+    /// A frame that was not committed yet is lost.
+    pub fn detach(&self) -> Option<Journal<F>> {
+        critical_section::with(|cs| self.state.borrow_ref_mut(cs).journal.take())
+    }
+
+    /// Calls `f` with the journal, e.g. to pass it to [`JournalReader::fill`], or returns `None`
+    /// if none is attached.
+    ///
+    /// Interrupts are disabled while `f` runs, and nothing can be logged from it.
+    pub fn with<R>(&self, f: impl FnOnce(&mut Journal<F>) -> R) -> Option<R> {
+        critical_section::with(|cs| self.state.borrow_ref_mut(cs).journal.as_mut().map(f))
+    }
+}
+
+impl<F> Default for JournalSink<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F: NorFlash + Send> Sink for JournalSink<F> {
+    fn write(&self, mut bytes: &[u8]) {
+        critical_section::with(|cs| {
+            let mut state = self.state.borrow_ref_mut(cs);
+            let SinkState { journal, failed } = &mut *state;
+            let Some(journal) = journal else {
+                return;
+            };
+            while !bytes.is_empty() {
+                let end = bytes
+                    .iter()
+                    .position(|&b| b == 0)
+                    .map_or(bytes.len(), |i| i + 1);
+                let (frame, rest) = bytes.split_at(end);
+                if !*failed {
+                    *failed = journal.write(frame).is_err();
+                }
+                if frame.ends_with(&[0]) {
+                    if !*failed {
+                        // A failed commit drops the frame all the same.
+                        journal.commit().ok();
+                    }
+                    *failed = false;
+                }
+                bytes = rest;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::RamFlash;
+    use super::*;
+
+    /// Appends a frame of `len - 1` times `byte` and its delimiter as one record.
+    fn record(journal: &mut Journal<&mut RamFlash>, byte: u8, len: usize) {
+        journal.write(&[byte; 1024][..len - 1]).unwrap();
+        journal.write(&[0]).unwrap();
+        journal.commit().unwrap();
+    }
+
+    /// Reads the frames committed to `journal` since the last call, without releasing them.
+    fn read_all(journal: &mut Journal<&mut RamFlash>, out: &mut [u8; 1024]) -> usize {
+        let mut buffer = JournalBuffer::<1024>::new();
+        let mut reader = JournalReader::new(&mut buffer);
+        reader.fill(journal).unwrap();
+        let grant = reader.consumer().read();
+        let mut len = 0;
+        for frame in grant.frames() {
+            let (first, second) = frame.bufs();
+            for bytes in [first, second, &[0]] {
+                out[len..len + bytes.len()].copy_from_slice(bytes);
+                len += bytes.len();
+            }
+        }
+        len
+    }
+
+    #[test]
+    fn write_and_recover() {
+        let mut flash = RamFlash([0; 1024]);
+        let mut journal = Journal::init(&mut flash, 0, 1024).unwrap();
+        let mut out = [0; 1024];
+        assert_eq!(read_all(&mut journal, &mut out), 0);
+
+        journal.write(&[1, 1, 0]).unwrap();
+        journal.write(&[2; 49]).unwrap();
+        journal.write(&[0]).unwrap();
+        journal.commit().unwrap();
+        // Not committed, so lost on reset, but part of it was already written.
+        journal.write(&[3; 130]).unwrap();
+        assert_eq!(read_all(&mut journal, &mut out), 53);
+
+        let mut journal = Journal::init(&mut flash, 0, 1024).unwrap();
+        assert_eq!(read_all(&mut journal, &mut out), 53);
+        assert_eq!(out[..3], [1, 1, 0]);
+        assert_eq!(out[3..52], [2; 49]);
+        assert_eq!(out[52], 0);
+
+        // The data of the lost record was written, so appending continues in a new sector.
+        record(&mut journal, 4, 10);
+        assert_eq!(journal.head, 1);
+        assert_eq!(read_all(&mut journal, &mut out), 10);
+        assert_eq!(out[..10], [4, 4, 4, 4, 4, 4, 4, 4, 4, 0]);
+
+        let mut journal = Journal::init(&mut flash, 0, 1024).unwrap();
+        assert_eq!(read_all(&mut journal, &mut out), 63);
+        assert_eq!(out[53..63], [4, 4, 4, 4, 4, 4, 4, 4, 4, 0]);
+    }
+
+    #[test]
+    fn wrap() {
+        let mut flash = RamFlash([0xff; 1024]);
+        let mut journal = Journal::init(&mut flash, 0, 1024).unwrap();
+        // Each record fills most of a sector, so every one opens a new sector.
+        for i in 1..7 {
+            record(&mut journal, i, 230);
+        }
+
+        // The oldest sectors were erased to make room.
+        let mut journal = Journal::init(&mut flash, 0, 1024).unwrap();
+        let mut out = [0; 1024];
+        assert_eq!(read_all(&mut journal, &mut out), 920);
+        for (i, frame) in out[..920].chunks(230).enumerate() {
+            assert_eq!(frame[..229], [i as u8 + 3; 229]);
+        }
+    }
+
+    #[test]
+    fn wrap_seq() {
+        let mut flash = RamFlash([0xff; 1024]);
+        let mut journal = Journal::init(&mut flash, 0, 1024).unwrap();
+        // The sectors get the sequence numbers `u32::MAX`, 0 and 1.
+        journal.seq = u32::MAX - 1;
+        for i in 1..4 {
+            record(&mut journal, i, 230);
+        }
+
+        let mut journal = Journal::init(&mut flash, 0, 1024).unwrap();
+        assert_eq!((journal.head, journal.seq, journal.tail), (2, 1, 0));
+        let mut out = [0; 1024];
+        assert_eq!(read_all(&mut journal, &mut out), 690);
+        for (i, frame) in out[..690].chunks(230).enumerate() {
+            assert_eq!(frame[..229], [i as u8 + 1; 229]);
+        }
+    }
+
+    #[test]
+    fn release() {
+        let mut flash = RamFlash([0xff; 1024]);
+        let mut journal = Journal::init(&mut flash, 0, 1024).unwrap();
+        for i in 1..4 {
+            record(&mut journal, i, 230);
+        }
+        let mut buffer = JournalBuffer::<1024>::new();
+        let mut reader = JournalReader::new(&mut buffer);
+        assert!(reader.fill(&mut journal).unwrap());
+        // Nothing is erased while the staged records are not released.
+        assert!(reader.fill(&mut journal).unwrap());
+        assert_eq!(reader.consumer().read().frames().count(), 3);
+        reader.consumer().read().release_all();
+        assert!(!reader.fill(&mut journal).unwrap());
+        record(&mut journal, 4, 10);
+
+        // The sectors before the one being read were erased.
+        let mut journal = Journal::init(&mut flash, 0, 1024).unwrap();
+        let mut out = [0; 1024];
+        assert_eq!(read_all(&mut journal, &mut out), 240);
+        assert_eq!(out[..229], [3; 229]);
+        assert_eq!(out[230..240], [4, 4, 4, 4, 4, 4, 4, 4, 4, 0]);
+    }
+
+    #[test]
+    fn small_buffer() {
+        let mut flash = RamFlash([0xff; 1024]);
+        let mut journal = Journal::init(&mut flash, 0, 1024).unwrap();
+        record(&mut journal, 1, 40);
+        record(&mut journal, 2, 40);
+        record(&mut journal, 3, 100);
+        record(&mut journal, 4, 8);
+
+        let mut buffer = JournalBuffer::<64>::new();
+        let mut reader = JournalReader::new(&mut buffer);
+        let mut frames = [0; 4];
+        for byte in &mut frames {
+            if !reader.fill(&mut journal).unwrap() {
+                break;
+            }
+            // The next record only fits once the staged one is released.
+            let grant = reader.consumer().read();
+            assert_eq!(grant.frames().count(), 1);
+            *byte = grant.frames().next().unwrap().bufs().0[0];
+            grant.release_all();
+        }
+        // The record that is larger than the buffer is dropped.
+        assert_eq!(frames, [1, 2, 4, 0]);
+        assert_eq!(reader.consumer().dropped_frames(), 1);
+    }
+
+    #[test]
+    fn sink() {
+        let mut flash = RamFlash([0xff; 1024]);
+        let sink = JournalSink::new();
+        // Dropped without a journal.
+        sink.write(&[9, 0]);
+        assert!(
+            sink.attach(Journal::init(&mut flash, 0, 1024).unwrap())
+                .is_none()
+        );
+
+        sink.write(&[0, 1, 1]);
+        sink.write(&[0, 2, 2, 0, 3]);
+        let mut out = [0; 1024];
+        assert_eq!(sink.with(|journal| read_all(journal, &mut out)), Some(6));
+        assert_eq!(out[..6], [1, 1, 0, 2, 2, 0]);
+
+        // The frame that was not committed is lost on reset.
+        let flash = sink.detach().unwrap().into_inner();
+        let mut journal = Journal::init(flash, 0, 1024).unwrap();
+        assert_eq!(read_all(&mut journal, &mut out), 6);
+        assert_eq!(out[..6], [1, 1, 0, 2, 2, 0]);
+    }
+
+    #[test]
+    fn too_small() {
+        let mut flash = RamFlash([0xff; 1024]);
+        assert_eq!(
+            Journal::init(&mut flash, 0, 256).err(),
+            Some(FlashError::TooSmall)
+        );
+    }
+}
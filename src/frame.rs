@@ -0,0 +1,340 @@
+//! Iteration over the zero-delimited frames stored in the ring buffer.
+//!
+//! With the `frame-crc` feature, the logger stores each frame as
+//! `[encoded frame][trailer][0x00]`, where the trailer holds 21 bits of the CRC-32 of the encoded
+//! frame. The trailer bytes all have the high bit set, so they never contain the zero delimiter
+//! used by rzCOBS.
+
+#[cfg(feature = "frame-crc")]
+use crate::crc::{CRC32_INIT, crc32_finish, crc32_update};
+use crate::ring_buffer::GrantR;
+
+/// Length of the CRC trailer appended to each frame.
+#[cfg(feature = "frame-crc")]
+pub(crate) const TRAILER_LEN: usize = 3;
+
+/// Encodes the low 21 bits of `crc` as the frame trailer, 7 bits per byte.
+#[cfg(feature = "frame-crc")]
+pub(crate) const fn trailer(crc: u32) -> [u8; TRAILER_LEN] {
+    [
+        0x80 | (crc & 0x7f) as u8,
+        0x80 | ((crc >> 7) & 0x7f) as u8,
+        0x80 | ((crc >> 14) & 0x7f) as u8,
+    ]
+}
+
+/// A complete frame, without its zero delimiter.
+///
+/// The frame is split in two parts if it wraps around the end of the ring.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame<'a> {
+    first: &'a [u8],
+    second: &'a [u8],
+}
+
+impl<'a> Frame<'a> {
+    /// Returns the bytes of the encoded frame.
+    ///
+    /// Append a zero byte to delimit the frame when forwarding it to a defmt decoder.
+    #[inline]
+    pub fn bufs(&self) -> (&'a [u8], &'a [u8]) {
+        (self.first, self.second)
+    }
+
+    /// Returns the length of the encoded frame.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.first.len() + self.second.len()
+    }
+
+    /// Returns `true` if the encoded frame is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Splits the frame into the bytes before and after `mid`.
+    #[cfg(feature = "frame-crc")]
+    fn split_at(self, mid: usize) -> (Frame<'a>, Frame<'a>) {
+        if let Some(mid) = mid.checked_sub(self.first.len()) {
+            let (second, rest) = self.second.split_at(mid);
+            (
+                Frame { second, ..self },
+                Frame {
+                    first: rest,
+                    second: &[],
+                },
+            )
+        } else {
+            let (first, rest) = self.first.split_at(mid);
+            (
+                Frame { first, second: &[] },
+                Frame {
+                    first: rest,
+                    ..self
+                },
+            )
+        }
+    }
+}
+
+/// Iterates the complete zero-delimited frames in a [`GrantR`].
+///
+/// Created by [`GrantR::frames`] and [`Consumer::peek_frames`](crate::Consumer::peek_frames).
+/// An incomplete frame at the end of the grant is not returned, it is returned by a later grant
+/// once the rest has been written. Empty frames, such as the separator the encoder writes before
+/// the first frame, are skipped.
+///
+/// With the `frame-crc` feature, the frames include their CRC trailer. Use
+/// `GrantR::verified_frames` to check and strip it.
+#[derive(Debug, Clone)]
+pub struct Frames<'a> {
+    first: &'a [u8],
+    second: &'a [u8],
+    /// Start of the next frame from the front, as an offset into `first` followed by `second`.
+    pos: usize,
+    /// End of the next frame from the back, just past its delimiter.
+    end: usize,
+}
+
+impl<'a> Frames<'a> {
+    /// Iterates the frames in `first` followed by `second`.
+    pub(crate) fn new(first: &'a [u8], second: &'a [u8]) -> Self {
+        let mut frames = Frames {
+            first,
+            second,
+            pos: 0,
+            end: first.len() + second.len(),
+        };
+        frames.end = frames.rfind_delimiter(0, frames.end).map_or(0, |d| d + 1);
+        frames
+    }
+
+    /// Returns the number of bytes covered by the frames iterated from the front so far.
+    ///
+    /// This includes delimiters and skipped frames. Pass it to [`GrantR::release`] to consume
+    /// the iterated frames.
+    #[inline]
+    pub fn consumed(&self) -> usize {
+        self.pos
+    }
+
+    /// Returns the parts of `first` followed by `second` in `start..end`.
+    fn parts(&self, start: usize, end: usize) -> (&'a [u8], &'a [u8]) {
+        let (first, second) = (self.first, self.second);
+        let split = first.len();
+        (
+            &first[start.min(split)..end.min(split)],
+            &second[start.max(split) - split..end.max(split) - split],
+        )
+    }
+
+    /// Returns the offset of the first zero delimiter in `start..end`.
+    fn find_delimiter(&self, start: usize, end: usize) -> Option<usize> {
+        let (first, second) = self.parts(start, end);
+        match first.iter().position(|&b| b == 0) {
+            Some(i) => Some(start + i),
+            None => Some(start + first.len() + second.iter().position(|&b| b == 0)?),
+        }
+    }
+
+    /// Returns the offset of the last zero delimiter in `start..end`.
+    fn rfind_delimiter(&self, start: usize, end: usize) -> Option<usize> {
+        let (first, second) = self.parts(start, end);
+        match second.iter().rposition(|&b| b == 0) {
+            Some(i) => Some(start + first.len() + i),
+            None => Some(start + first.iter().rposition(|&b| b == 0)?),
+        }
+    }
+}
+
+impl<'a> Iterator for Frames<'a> {
+    type Item = Frame<'a>;
+
+    fn next(&mut self) -> Option<Frame<'a>> {
+        while self.pos < self.end {
+            let start = self.pos;
+            // There is always a delimiter at `end - 1`.
+            let delimiter = self.find_delimiter(start, self.end)?;
+            self.pos = delimiter + 1;
+            if delimiter > start {
+                let (first, second) = self.parts(start, delimiter);
+                return Some(Frame { first, second });
+            }
+        }
+        None
+    }
+}
+
+impl<'a> DoubleEndedIterator for Frames<'a> {
+    fn next_back(&mut self) -> Option<Frame<'a>> {
+        while self.pos < self.end {
+            let delimiter = self.end - 1;
+            let start = self
+                .rfind_delimiter(self.pos, delimiter)
+                .map_or(self.pos, |d| d + 1);
+            self.end = start;
+            if delimiter > start {
+                let (first, second) = self.parts(start, delimiter);
+                return Some(Frame { first, second });
+            }
+        }
+        None
+    }
+}
+
+/// Peeks at the complete frames in the buffer without consuming them.
+///
+/// Created by [`Consumer::peek_frames`](crate::Consumer::peek_frames). Holds a read grant until
+/// dropped, so the frames cannot be overwritten while they are iterated.
+pub struct PeekFrames<'a, 'c> {
+    _grant: GrantR<'a, 'c>,
+    frames: Frames<'a>,
+}
+
+impl<'a, 'c> PeekFrames<'a, 'c> {
+    /// Iterates `frames` while holding the `grant` they were taken from.
+    pub(crate) fn new(grant: GrantR<'a, 'c>, frames: Frames<'a>) -> Self {
+        PeekFrames {
+            _grant: grant,
+            frames,
+        }
+    }
+}
+
+impl<'a> Iterator for PeekFrames<'a, '_> {
+    type Item = Frame<'a>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Frame<'a>> {
+        self.frames.next()
+    }
+}
+
+impl<'a> DoubleEndedIterator for PeekFrames<'a, '_> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Frame<'a>> {
+        self.frames.next_back()
+    }
+}
+
+/// Iterates the complete frames of a [`GrantR`], skipping frames with a bad CRC trailer.
+///
+/// Created by [`GrantR::verified_frames`], or by `host::Dump::verified_frames` with the `host`
+/// feature. An incomplete frame at the end of the grant ends the iteration, it is returned by a
+/// later grant once the rest has been written.
+#[cfg(feature = "frame-crc")]
+pub struct VerifiedFrames<'a> {
+    frames: Frames<'a>,
+    discarded: usize,
+}
+
+#[cfg(feature = "frame-crc")]
+impl<'a> VerifiedFrames<'a> {
+    /// Iterates the frames in `first` followed by `second`.
+    pub(crate) fn new(first: &'a [u8], second: &'a [u8]) -> Self {
+        VerifiedFrames {
+            frames: Frames::new(first, second),
+            discarded: 0,
+        }
+    }
+
+    /// Returns the number of frames skipped so far because their trailer did not match.
+    #[inline]
+    pub fn discarded(&self) -> usize {
+        self.discarded
+    }
+
+    /// Returns the number of bytes covered by the frames iterated so far.
+    ///
+    /// This includes trailers, delimiters and discarded frames. Pass it to
+    /// [`GrantR::release`] to consume the iterated frames.
+    #[inline]
+    pub fn consumed(&self) -> usize {
+        self.frames.consumed()
+    }
+}
+
+#[cfg(feature = "frame-crc")]
+impl<'a> Iterator for VerifiedFrames<'a> {
+    type Item = Frame<'a>;
+
+    fn next(&mut self) -> Option<Frame<'a>> {
+        loop {
+            let frame = self.frames.next()?;
+            let Some(body_len) = frame.len().checked_sub(TRAILER_LEN) else {
+                // Too short to hold a trailer.
+                self.discarded += 1;
+                continue;
+            };
+
+            let (body, stored) = frame.split_at(body_len);
+            let (first, second) = body.bufs();
+            let crc = crc32_finish(crc32_update(crc32_update(CRC32_INIT, first), second));
+
+            let (t1, t2) = stored.bufs();
+            let mut stored = [0; TRAILER_LEN];
+            stored[..t1.len()].copy_from_slice(t1);
+            stored[t1.len()..].copy_from_slice(t2);
+
+            if stored == trailer(crc) {
+                return Some(body);
+            }
+            self.discarded += 1;
+        }
+    }
+}
+
+impl GrantR<'_, '_> {
+    /// Iterates the complete frames in this grant.
+    ///
+    /// Release [`Frames::consumed`] bytes afterwards to consume the iterated frames, or drop the
+    /// grant to leave them in the buffer.
+    #[inline]
+    pub fn frames(&self) -> Frames<'_> {
+        let (first, second) = self.bufs();
+        Frames::new(first, second)
+    }
+
+    /// Returns the longest prefix of this grant that ends on a frame boundary and is at most
+    /// `max_len` bytes long, including the delimiters.
+    ///
+    /// Use this to fill fixed-size packets, e.g. on BLE or CAN-FD, without splitting frames.
+    /// The prefix is empty if the first frame is longer than `max_len`, such a frame must be
+    /// sent in pieces or skipped with [`GrantR::release_frames`].
+    pub fn frames_prefix(&self, max_len: usize) -> (&[u8], &[u8]) {
+        let mut frames = self.frames();
+        let mut len = 0;
+        while frames.next().is_some() && frames.consumed() <= max_len {
+            len = frames.consumed();
+        }
+
+        let (first, second) = self.bufs();
+        let split = len.min(first.len());
+        (&first[..split], &second[..len - split])
+    }
+
+    /// Finish the read, marking the first `n` complete frames as used.
+    ///
+    /// Returns the number of frames released, which is less than `n` if the grant holds fewer
+    /// complete frames.
+    pub fn release_frames(self, n: usize) -> usize {
+        let mut frames = self.frames();
+        let released = frames.by_ref().take(n).count();
+        let used = frames.consumed();
+        self.release(used);
+        released
+    }
+
+    /// Iterates the complete frames in this grant, verifying their CRC trailers.
+    ///
+    /// Frames with a bad trailer, e.g. corrupted while the device was off, are skipped and
+    /// counted in [`VerifiedFrames::discarded`]. Release [`VerifiedFrames::consumed`] bytes
+    /// afterwards to consume the iterated frames.
+    #[cfg(feature = "frame-crc")]
+    #[inline]
+    pub fn verified_frames(&self) -> VerifiedFrames<'_> {
+        let (first, second) = self.bufs();
+        VerifiedFrames::new(first, second)
+    }
+}
@@ -0,0 +1,423 @@
+//! Parsing of raw persist region dumps on the host, enabled by the `host` feature.
+//!
+//! A dump holds the whole region between `__defmt_persist_start` and `__defmt_persist_end`, as
+//! read from a 32-bit ARM target, e.g. from a core dump or a debugger memory read. [`parse`]
+//! validates it the same way [`crate::init`] does after a reset, without modifying it, and
+//! returns the logs that would be recovered.
+
+use core::fmt;
+use std::vec::Vec;
+
+#[cfg(feature = "frame-crc")]
+use crate::VerifiedFrames;
+use crate::ring_buffer::{
+    ARM_SIZE_COMPACT, ARM_SIZE_COMPACT_ECC, ARM_SIZE_COMPACT_WORD64, ARM_SIZE_COMPACT_WORD64_ECC,
+    ARM_SIZE_DEFAULT, ARM_SIZE_ECC, ARM_SIZE_WORD64, ARM_SIZE_WORD64_ECC, MAGIC_COMPACT,
+    MAGIC_COMPACT_ECC, MAGIC_COMPACT_WORD64, MAGIC_COMPACT_WORD64_ECC, MAGIC_DEFAULT, MAGIC_ECC,
+    MAGIC_WORD64, MAGIC_WORD64_ECC, META, Meta, READ_LOCK, offsets, repair_indices,
+};
+use crate::{LogLevel, RecoveryStatus, ResetReason, crash_ring_offset};
+
+/// Alignment of the ring buffer header on 32-bit ARM.
+const ARM_ALIGN: usize = 8;
+
+/// Error returned by [`parse`] when the dump holds no recoverable logs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// The dump does not start with a valid header, so [`crate::init`] would reinitialize the
+    /// buffer.
+    BadMagic,
+    /// The dump is too small to hold the header plus data.
+    TooSmall,
+    /// The buffer size would overflow pointer arithmetic on the target.
+    TooLarge,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ParseError::BadMagic => "no valid defmt-persist header",
+            ParseError::TooSmall => "dump is too small for the header plus data",
+            ParseError::TooLarge => "dump is too large for a persist region",
+        })
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A parsed persist region dump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dump<'a> {
+    /// How [`crate::init`] would recover the buffer.
+    ///
+    /// This is never [`RecoveryStatus::Reinitialized`], which is reported as
+    /// [`ParseError::BadMagic`] instead.
+    pub recovery_status: RecoveryStatus,
+    /// Whether the firmware was built with the `ecc` feature.
+    pub ecc: bool,
+    /// Whether the firmware was built with the `compact-header` feature.
+    ///
+    /// Such firmware does not keep the reset reason and the firmware identifier, and its
+    /// counters wrap early, see the `Tiny Regions` section of the README.
+    pub compact_header: bool,
+    /// Whether the firmware was built with the `word-write` feature and 64-bit words, which
+    /// gives each header field a word of its own.
+    pub word64: bool,
+    /// Persisted dropped frames counter, see `ConsumerAndMetadata::dropped_frames`.
+    pub dropped_frames: u32,
+    /// Boot counter of the run that wrote the dump, see `ConsumerAndMetadata::boot_count`.
+    pub boot_count: u32,
+    /// Reset reason recorded by the run that wrote the dump, which tells why the run before it
+    /// ended.
+    pub reset_reason: ResetReason,
+    /// Firmware identifier recorded by the run that wrote the dump, see
+    /// `ConsumerAndMetadata::firmware_id`.
+    pub firmware_id: u32,
+    /// Runtime log level stored by the run that wrote the dump, see
+    /// `Consumer::set_log_level`.
+    pub log_level: LogLevel,
+    first: &'a [u8],
+    second: &'a [u8],
+}
+
+impl<'a> Dump<'a> {
+    /// Returns the readable bytes, split in two parts if they wrap around the end of the ring.
+    #[inline]
+    pub fn bufs(&self) -> (&'a [u8], &'a [u8]) {
+        (self.first, self.second)
+    }
+
+    /// Returns the readable bytes as a single stream, ready for a defmt decoder.
+    ///
+    /// If the firmware was built with the `frame-crc` feature, use
+    /// `Dump::verified_frames` instead, as the stream contains the CRC trailers.
+    pub fn to_vec(&self) -> Vec<u8> {
+        [self.first, self.second].concat()
+    }
+
+    /// Iterates the complete frames, verifying the CRC trailers written with the `frame-crc`
+    /// feature.
+    #[cfg(feature = "frame-crc")]
+    #[inline]
+    pub fn verified_frames(&self) -> VerifiedFrames<'a> {
+        VerifiedFrames::new(self.first, self.second)
+    }
+}
+
+/// Parses a raw dump of the persist region.
+///
+/// The layout is detected from the header, so dumps from firmware built with and without the
+/// `ecc` and `compact-header` features, and with 64-bit words, are all accepted. A `READ_LOCK`
+/// bit left in the read index by the `overwrite` feature is ignored.
+///
+/// # Errors
+///
+/// Returns an error if the dump holds no recoverable logs, see [`ParseError`].
+pub fn parse(dump: &[u8]) -> Result<Dump<'_>, ParseError> {
+    let header = dump
+        .get(offsets::HEADER..offsets::HEADER + size_of::<u128>())
+        .ok_or(ParseError::TooSmall)?;
+    let compact = u32::from_le_bytes(header[..4].try_into().unwrap());
+    // The fields after the magic follow each other, so only their start and their stride depend
+    // on the layout. With 64-bit words, each field takes a word.
+    let (ecc, compact_header, word64, size) = match compact & !META {
+        MAGIC_COMPACT => (false, true, false, ARM_SIZE_COMPACT),
+        MAGIC_COMPACT_ECC => (true, true, false, ARM_SIZE_COMPACT_ECC),
+        MAGIC_COMPACT_WORD64 => (false, true, true, ARM_SIZE_COMPACT_WORD64),
+        MAGIC_COMPACT_WORD64_ECC => (true, true, true, ARM_SIZE_COMPACT_WORD64_ECC),
+        _ => match u128::from_le_bytes(header.try_into().unwrap()) {
+            MAGIC_DEFAULT => (false, false, false, ARM_SIZE_DEFAULT),
+            MAGIC_ECC => (true, false, false, ARM_SIZE_ECC),
+            MAGIC_WORD64 => (false, false, true, ARM_SIZE_WORD64),
+            MAGIC_WORD64_ECC => (true, false, true, ARM_SIZE_WORD64_ECC),
+            _ => return Err(ParseError::BadMagic),
+        },
+    };
+    let stride = if word64 { 8 } else { offsets::INDEX_SIZE };
+    let fields_start = if compact_header {
+        stride
+    } else {
+        size_of::<u128>()
+    };
+    if dump.len() <= size {
+        return Err(ParseError::TooSmall);
+    }
+    let word = |i: usize| {
+        let offset = fields_start + i * stride;
+        let bytes = &dump[offset..offset + offsets::INDEX_SIZE];
+        u32::from_le_bytes(bytes.try_into().unwrap())
+    };
+    let buf = &dump[size..];
+    if buf.len() >= i32::MAX as usize / 4 {
+        return Err(ParseError::TooLarge);
+    }
+
+    // The metadata follows the indexes, or is packed next to a compact magic.
+    let (read, write) = (word(0) & !READ_LOCK, word(1));
+    let (meta, (read, write, recovery_status)) = if compact_header {
+        let packed = compact & META;
+        let meta = [
+            Meta::Dropped,
+            Meta::BootCount,
+            Meta::ResetReason,
+            Meta::FirmwareId,
+            Meta::LogLevel,
+        ]
+        .map(|meta| meta.unpack(packed));
+        (
+            meta,
+            repair_indices(&[read, write, packed], word(2), buf.len()),
+        )
+    } else {
+        let fields = [read, write, word(2), word(3), word(4), word(5), word(6)];
+        let meta = [fields[2], fields[3], fields[4], fields[5], fields[6]];
+        (meta, repair_indices(&fields, word(7), buf.len()))
+    };
+
+    let (read, write) = (read as usize, write as usize);
+    let (first, second) = if read <= write {
+        (&buf[read..write], &buf[..0])
+    } else {
+        (&buf[read..], &buf[..write])
+    };
+
+    Ok(Dump {
+        recovery_status,
+        ecc,
+        compact_header,
+        word64,
+        dropped_frames: meta[0],
+        boot_count: meta[1],
+        reset_reason: ResetReason::from_bits(meta[2]),
+        firmware_id: meta[3],
+        log_level: LogLevel::from_bits(meta[4]),
+        first,
+        second,
+    })
+}
+
+/// Splits a dump from firmware built with the `crash-ring` feature into the live ring and the
+/// crash ring, each of which can be passed to [`parse`].
+///
+/// `percent` is the share of the region used by the crash ring, as set with
+/// `DEFMT_PERSIST_CRASH_PERCENT` when building the firmware, 25 by default.
+pub fn split_crash_ring(dump: &[u8], percent: u8) -> (&[u8], &[u8]) {
+    dump.split_at(crash_ring_offset(dump.len(), percent as usize, ARM_ALIGN))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ring_buffer::checksum;
+    use std::vec;
+
+    /// Builds a dump with a valid header and a 16 byte buffer holding `0..16`.
+    ///
+    /// The header fields follow `magic`, as on 32-bit ARM.
+    fn dump(magic: &[u8], size: usize, read: u32, write: u32) -> Vec<u8> {
+        dump_with_stride(magic, size, offsets::INDEX_SIZE, read, write)
+    }
+
+    /// Builds a dump like [`dump`] with the header fields `stride` bytes apart.
+    fn dump_with_stride(
+        magic: &[u8],
+        size: usize,
+        stride: usize,
+        read: u32,
+        write: u32,
+    ) -> Vec<u8> {
+        let fields = [
+            read,
+            write,
+            1,
+            2,
+            ResetReason::Watchdog.to_bits(),
+            0xabcd,
+            LogLevel::Info.to_bits(),
+        ];
+        let mut dump = vec![0; size];
+        dump[..magic.len()].copy_from_slice(magic);
+        for (i, value) in fields.into_iter().chain([checksum(&fields)]).enumerate() {
+            let offset = magic.len() + i * stride;
+            dump[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        dump.extend(0..16);
+        dump
+    }
+
+    /// Builds a dump like [`dump`] with a compact header.
+    fn compact_dump(magic: u32, size: usize, read: u32, write: u32) -> Vec<u8> {
+        compact_dump_with_stride(magic, size, offsets::INDEX_SIZE, read, write)
+    }
+
+    /// Builds a dump like [`compact_dump`] with the header fields `stride` bytes apart.
+    fn compact_dump_with_stride(
+        magic: u32,
+        size: usize,
+        stride: usize,
+        read: u32,
+        write: u32,
+    ) -> Vec<u8> {
+        let meta = 1 << 11 | LogLevel::Info.to_bits() << 8 | 2;
+        let fields = [read, write, meta];
+        let mut dump = vec![0; size];
+        for (i, value) in [magic | meta, read, write, checksum(&fields)]
+            .into_iter()
+            .enumerate()
+        {
+            let offset = i * stride;
+            dump[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        dump.extend(0..16);
+        dump
+    }
+
+    #[test]
+    fn parse_valid() {
+        let dump = dump(&MAGIC_DEFAULT.to_le_bytes(), ARM_SIZE_DEFAULT, 2, 5);
+        let parsed = parse(&dump).unwrap();
+        assert_eq!(parsed.recovery_status, RecoveryStatus::Valid);
+        assert!(!parsed.ecc);
+        assert_eq!(parsed.dropped_frames, 1);
+        assert_eq!(parsed.boot_count, 2);
+        assert_eq!(parsed.reset_reason, ResetReason::Watchdog);
+        assert_eq!(parsed.firmware_id, 0xabcd);
+        assert_eq!(parsed.log_level, LogLevel::Info);
+        assert_eq!(parsed.bufs(), (&[2, 3, 4][..], &[][..]));
+    }
+
+    #[test]
+    fn parse_crossing_end() {
+        let dump = dump(&MAGIC_ECC.to_le_bytes(), ARM_SIZE_ECC, 14, 2);
+        let parsed = parse(&dump).unwrap();
+        assert_eq!(parsed.recovery_status, RecoveryStatus::Valid);
+        assert!(parsed.ecc);
+        assert_eq!(parsed.bufs(), (&[14, 15][..], &[0, 1][..]));
+        assert_eq!(parsed.to_vec(), [14, 15, 0, 1]);
+    }
+
+    #[test]
+    fn parse_compact_header() {
+        let compact = compact_dump(MAGIC_COMPACT, ARM_SIZE_COMPACT, 2, 5);
+        let parsed = parse(&compact).unwrap();
+        assert_eq!(parsed.recovery_status, RecoveryStatus::Valid);
+        assert!(parsed.compact_header);
+        assert!(!parsed.ecc);
+        assert_eq!(parsed.dropped_frames, 1);
+        assert_eq!(parsed.boot_count, 2);
+        assert_eq!(parsed.reset_reason, ResetReason::Unknown);
+        assert_eq!(parsed.firmware_id, 0);
+        assert_eq!(parsed.log_level, LogLevel::Info);
+        assert_eq!(parsed.bufs(), (&[2, 3, 4][..], &[][..]));
+
+        let compact = compact_dump(MAGIC_COMPACT_ECC, ARM_SIZE_COMPACT_ECC, 14, 2);
+        let parsed = parse(&compact).unwrap();
+        assert!(parsed.compact_header);
+        assert!(parsed.ecc);
+        assert_eq!(parsed.bufs(), (&[14, 15][..], &[0, 1][..]));
+
+        // The metadata is covered by the checksum.
+        let mut compact = compact_dump(MAGIC_COMPACT, ARM_SIZE_COMPACT, 2, 5);
+        compact[0] ^= 1;
+        let parsed = parse(&compact).unwrap();
+        assert_eq!(parsed.recovery_status, RecoveryStatus::Discarded);
+
+        let mut bad_magic = compact_dump(MAGIC_COMPACT, ARM_SIZE_COMPACT, 2, 5);
+        bad_magic[3] ^= 1;
+        assert_eq!(parse(&bad_magic), Err(ParseError::BadMagic));
+    }
+
+    #[test]
+    fn parse_word64() {
+        let dump = dump_with_stride(&MAGIC_WORD64.to_le_bytes(), ARM_SIZE_WORD64, 8, 2, 5);
+        let parsed = parse(&dump).unwrap();
+        assert_eq!(parsed.recovery_status, RecoveryStatus::Valid);
+        assert!(parsed.word64);
+        assert!(!parsed.ecc);
+        assert_eq!(parsed.dropped_frames, 1);
+        assert_eq!(parsed.firmware_id, 0xabcd);
+        assert_eq!(parsed.log_level, LogLevel::Info);
+        assert_eq!(parsed.bufs(), (&[2, 3, 4][..], &[][..]));
+
+        let dump = dump_with_stride(
+            &MAGIC_WORD64_ECC.to_le_bytes(),
+            ARM_SIZE_WORD64_ECC,
+            8,
+            14,
+            2,
+        );
+        let parsed = parse(&dump).unwrap();
+        assert!(parsed.word64);
+        assert!(parsed.ecc);
+        assert_eq!(parsed.bufs(), (&[14, 15][..], &[0, 1][..]));
+
+        let compact =
+            compact_dump_with_stride(MAGIC_COMPACT_WORD64, ARM_SIZE_COMPACT_WORD64, 8, 2, 5);
+        let parsed = parse(&compact).unwrap();
+        assert_eq!(parsed.recovery_status, RecoveryStatus::Valid);
+        assert!(parsed.compact_header && parsed.word64);
+        assert_eq!(parsed.boot_count, 2);
+        assert_eq!(parsed.bufs(), (&[2, 3, 4][..], &[][..]));
+
+        let compact = compact_dump_with_stride(
+            MAGIC_COMPACT_WORD64_ECC,
+            ARM_SIZE_COMPACT_WORD64_ECC,
+            8,
+            14,
+            2,
+        );
+        let parsed = parse(&compact).unwrap();
+        assert!(parsed.compact_header && parsed.word64 && parsed.ecc);
+        assert_eq!(parsed.bufs(), (&[14, 15][..], &[0, 1][..]));
+    }
+
+    #[test]
+    fn parse_read_lock() {
+        let mut dump = dump(&MAGIC_DEFAULT.to_le_bytes(), ARM_SIZE_DEFAULT, 2, 5);
+        dump[size_of::<u128>() + 3] |= 0x80;
+        let parsed = parse(&dump).unwrap();
+        assert_eq!(parsed.recovery_status, RecoveryStatus::Valid);
+        assert_eq!(parsed.bufs(), (&[2, 3, 4][..], &[][..]));
+    }
+
+    #[test]
+    fn parse_flipped_index() {
+        let mut dump = dump(&MAGIC_DEFAULT.to_le_bytes(), ARM_SIZE_DEFAULT, 2, 5);
+        dump[size_of::<u128>() + offsets::INDEX_SIZE] ^= 8;
+        let parsed = parse(&dump).unwrap();
+        assert_eq!(parsed.recovery_status, RecoveryStatus::IndicesRepaired);
+        assert_eq!(parsed.bufs(), (&[2, 3, 4][..], &[][..]));
+    }
+
+    #[test]
+    fn parse_discarded() {
+        let mut dump = dump(&MAGIC_DEFAULT.to_le_bytes(), ARM_SIZE_DEFAULT, 2, 5);
+        dump[size_of::<u128>() + 2 * offsets::INDEX_SIZE] ^= 1;
+        let parsed = parse(&dump).unwrap();
+        assert_eq!(parsed.recovery_status, RecoveryStatus::Discarded);
+        assert_eq!(parsed.bufs(), (&[][..], &[][..]));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let mut bad_magic = dump(&MAGIC_DEFAULT.to_le_bytes(), ARM_SIZE_DEFAULT, 2, 5);
+        bad_magic[0] ^= 1;
+        assert_eq!(parse(&bad_magic), Err(ParseError::BadMagic));
+
+        let mut header_only = dump(&MAGIC_DEFAULT.to_le_bytes(), ARM_SIZE_DEFAULT, 2, 5);
+        header_only.truncate(ARM_SIZE_DEFAULT);
+        assert_eq!(parse(&header_only), Err(ParseError::TooSmall));
+        assert_eq!(parse(&[0; 8]), Err(ParseError::TooSmall));
+    }
+
+    #[test]
+    fn split_crash_ring() {
+        // 64 bytes of live ring followed by 72 bytes of crash ring, 53% of the region.
+        let mut region = dump(&MAGIC_DEFAULT.to_le_bytes(), ARM_SIZE_DEFAULT, 2, 5);
+        region.extend(dump(&MAGIC_ECC.to_le_bytes(), ARM_SIZE_ECC, 14, 2));
+
+        let (live, crash) = super::split_crash_ring(&region, 53);
+        assert_eq!(parse(live).unwrap().bufs(), (&[2, 3, 4][..], &[][..]));
+        assert_eq!(parse(crash).unwrap().bufs(), (&[14, 15][..], &[0, 1][..]));
+    }
+}
@@ -2,24 +2,46 @@
 #![warn(missing_docs)]
 #![doc = include_str!("../README.md")]
 
-use core::mem::{align_of, size_of};
+use core::mem::{MaybeUninit, align_of, size_of};
+use core::ops::Range;
 use core::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "frame-crc")]
+pub use frame::VerifiedFrames;
+pub use frame::{Frame, Frames, PeekFrames};
+#[cfg(feature = "rtt")]
+pub use logger::rtt::{RttDownChannel, RttUpChannel};
 use ring_buffer::RingBuffer;
-#[cfg(feature = "qemu-test")]
+#[cfg(any(feature = "qemu-test", feature = "host"))]
 pub use ring_buffer::offsets;
 pub use ring_buffer::{Consumer, GrantR};
+pub use sink::{MAX_SINKS, Sink, TooManySinks, add_sink};
+
+#[cfg(feature = "host")]
+extern crate std;
 
 #[cfg(feature = "async-await")]
 pub(crate) mod atomic_waker;
+mod crc;
+#[cfg(feature = "dcache")]
+mod dcache;
+#[cfg(feature = "flash")]
+pub mod flash;
+mod frame;
+#[cfg(feature = "host")]
+pub mod host;
 pub(crate) mod logger;
 mod ring_buffer;
+mod sink;
+#[cfg(feature = "tail")]
+pub mod tail;
 
-/// Error returned by [`init`] when initialization fails.
+/// Error returned by [`init`] and [`init_with_region`] when initialization fails.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
 pub enum InitError {
-    /// [`init`] has already been called.
+    /// The logger has already been initialized.
     AlreadyInitialized,
-    /// Memory region is not properly aligned for the ring buffer header.
+    /// Memory region is not properly aligned for the ring buffer header, or with the
+    /// `word-write` feature, the data after it does not consist of whole, aligned words.
     BadAlignment,
     /// Memory region is too small to hold the ring buffer header plus data.
     TooSmall,
@@ -27,16 +49,187 @@ pub enum InitError {
     TooLarge,
 }
 
+/// How [`init`] recovered the persisted buffer state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum RecoveryStatus {
+    /// The header, checksum and indexes were valid, and the buffer contents were recovered.
+    Valid,
+    /// The header was valid, but the checksum did not match, e.g. after a reset while an index
+    /// was updated.
+    ///
+    /// The indexes were repaired from the checksum and the recovered logs were kept, but the
+    /// newest frame may be missing. The persisted counters were kept, but may be corrupt.
+    IndicesRepaired,
+    /// The header was valid, but the indexes could not be repaired.
+    ///
+    /// The recovered logs were discarded. The persisted counters were kept, but may be corrupt
+    /// as well.
+    Discarded,
+    /// No valid header was found, and the buffer was initialized empty.
+    Reinitialized,
+}
+
+/// Why the MCU was reset, as reported by the application to [`init_with_reset_reason`].
+///
+/// Reading the reason is chip-specific, e.g. from `RCC_CSR` on STM32 or `RESETREAS` on nRF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum ResetReason {
+    /// The reset reason was not recorded.
+    Unknown,
+    /// Power-on reset.
+    PowerOn,
+    /// Reset pin.
+    Pin,
+    /// Software reset, e.g. `SCB::sys_reset`.
+    Software,
+    /// Watchdog timeout.
+    Watchdog,
+    /// Brown-out detection.
+    BrownOut,
+    /// CPU lockup.
+    Lockup,
+    /// A chip-specific reason not covered by the other variants.
+    Other(u8),
+}
+
+impl ResetReason {
+    /// Encodes the reason for the persisted header.
+    const fn to_bits(self) -> u32 {
+        match self {
+            ResetReason::Unknown => 0,
+            ResetReason::PowerOn => 1,
+            ResetReason::Pin => 2,
+            ResetReason::Software => 3,
+            ResetReason::Watchdog => 4,
+            ResetReason::BrownOut => 5,
+            ResetReason::Lockup => 6,
+            ResetReason::Other(code) => 0x100 | code as u32,
+        }
+    }
+
+    /// Decodes the reason from the persisted header. Unknown values decode as
+    /// [`ResetReason::Unknown`].
+    #[cfg(feature = "host")]
+    const fn from_bits(bits: u32) -> Self {
+        match bits {
+            1 => ResetReason::PowerOn,
+            2 => ResetReason::Pin,
+            3 => ResetReason::Software,
+            4 => ResetReason::Watchdog,
+            5 => ResetReason::BrownOut,
+            6 => ResetReason::Lockup,
+            0x100..=0x1ff => ResetReason::Other(bits as u8),
+            _ => ResetReason::Unknown,
+        }
+    }
+}
+
+/// Minimum level of the frames stored in the buffer, set at runtime with
+/// [`Consumer::set_log_level`], or sent to RTT, set with `set_rtt_log_level`.
+///
+/// This filters on top of the compile-time `DEFMT_LOG` filter. Frames without a level, such as
+/// those from `defmt::println!`, are always stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, defmt::Format)]
+pub enum LogLevel {
+    /// Store all frames.
+    Trace,
+    /// Store debug frames and above.
+    Debug,
+    /// Store info frames and above.
+    Info,
+    /// Store warn and error frames.
+    Warn,
+    /// Store error frames only.
+    Error,
+}
+
+impl LogLevel {
+    /// Encodes the level for the persisted header.
+    const fn to_bits(self) -> u32 {
+        self as u32
+    }
+
+    /// Decodes the level from the persisted header. Unknown values decode as
+    /// [`LogLevel::Trace`], so no logs are lost to a corrupt level.
+    const fn from_bits(bits: u32) -> Self {
+        match bits {
+            1 => LogLevel::Debug,
+            2 => LogLevel::Info,
+            3 => LogLevel::Warn,
+            4 => LogLevel::Error,
+            _ => LogLevel::Trace,
+        }
+    }
+}
+
 /// Holds the log reader and some additional information from initialization.
 pub struct ConsumerAndMetadata<'a> {
     /// Reads logs from the buffer.
     pub consumer: Consumer<'a>,
+    /// Reads logs from the crash ring, written after [`set_crashing`].
+    ///
+    /// Everything in the crash ring at initialization was recovered from a previous run.
+    #[cfg(feature = "crash-ring")]
+    pub crash_consumer: Consumer<'a>,
+    /// How the persisted crash ring state was recovered.
+    #[cfg(feature = "crash-ring")]
+    pub crash_recovery_status: RecoveryStatus,
     /// Number of bytes that were not from the current run.
     ///
     /// If the recovered logs were produced by a different firmware,
     /// different decoders need to be used. This field helps identify the
-    /// data that was definitely produced by the current firmware.
+    /// data that was definitely produced by the current firmware. Compare
+    /// [`Self::previous_firmware_id`] with [`Self::firmware_id`] to tell
+    /// whether the recovered logs need a different decoder.
+    ///
+    /// This is 0 after [`init_with_snapshot`], which moves the recovered logs out of the buffer.
     pub recovered_logs_len: usize,
+    /// Number of recovered bytes copied into the snapshot buffer by [`init_with_snapshot`].
+    ///
+    /// This is 0 for the other `init` functions.
+    pub snapshot_len: usize,
+    /// How the persisted buffer state was recovered.
+    pub recovery_status: RecoveryStatus,
+    /// Number of frames dropped because the buffer was full, before this run.
+    ///
+    /// The counter persists across resets until the buffer is reinitialized. Frames dropped
+    /// during this run are available from [`Consumer::dropped_frames`], and are also reported
+    /// in the log stream by a message once space becomes available again. With the
+    /// `compact-header` feature, the counter wraps at 32.
+    pub dropped_frames: u32,
+    /// Number of times the buffer was recovered since it was initialized.
+    ///
+    /// This is 0 when the buffer was (re)initialized during this run. The same value is
+    /// logged in the session marker frame written by [`init`]. With the `compact-header`
+    /// feature, the counter wraps at 256.
+    pub boot_count: u32,
+    /// Why the previous run, which produced the recovered logs, ended.
+    ///
+    /// This is the reason passed to [`init_with_reset_reason`] by this run, which is also
+    /// stored in the persisted header. It is [`ResetReason::Unknown`] if the buffer was
+    /// reinitialized during this run, as there is no previous run, or if [`init`] was used.
+    pub previous_reset_reason: ResetReason,
+    /// Identifier of the running firmware, stored in the persisted header.
+    ///
+    /// With the `firmware-id` feature, this is the CRC-32 of the bytes between the linker
+    /// symbols `__defmt_persist_firmware_id_start` and `__defmt_persist_firmware_id_end`,
+    /// e.g. the GNU build-id note. Host tools can compute the same value from the ELF file.
+    /// Without the feature, it is 0.
+    pub firmware_id: u32,
+    /// Identifier of the firmware that ran before this one, see [`Self::firmware_id`].
+    ///
+    /// This is 0 if the buffer was (re)initialized during this run, if the previous firmware
+    /// did not record an identifier, or with the `compact-header` feature, which does not keep
+    /// it.
+    pub previous_firmware_id: u32,
+    /// Whether the persist region is write-back cacheable and the data cache is enabled.
+    ///
+    /// The `dcache` feature cleans the cache after every store to the region, so nothing is
+    /// lost on reset, at some cost per log frame. Marking the region non-cacheable via the MPU
+    /// avoids that cost. Only the data TCM and the MPU of the Cortex-M7 are taken into account,
+    /// other cores with a data cache are checked against the default memory map.
+    #[cfg(feature = "dcache")]
+    pub cacheable: bool,
 }
 
 /// Initialize the logger.
@@ -45,10 +238,14 @@ pub struct ConsumerAndMetadata<'a> {
 /// `__defmt_persist_end`. Define these in your linker script to reserve memory for
 /// the persist buffer.
 ///
+/// After initialization, a session marker frame `defmt-persist: boot {=u32}, reset reason: {}`
+/// is logged with [`ConsumerAndMetadata::boot_count`], so host tools can split the stream per
+/// boot. The reset reason is [`ResetReason::Unknown`], use [`init_with_reset_reason`] to record it.
+///
 /// # Errors
 ///
 /// Returns an error if:
-/// - [`InitError::AlreadyInitialized`]: Called more than once
+/// - [`InitError::AlreadyInitialized`]: Called more than once, or after another `init` function
 /// - [`InitError::BadAlignment`]: Memory region is not properly aligned
 /// - [`InitError::TooSmall`]: Memory region is too small for the header plus data
 /// - [`InitError::TooLarge`]: Buffer size would overflow pointer arithmetic
@@ -59,53 +256,432 @@ pub struct ConsumerAndMetadata<'a> {
 /// other purpose. It is safe for both a bootloader and application to call this,
 /// provided the bootloader terminates before the application starts.
 ///
-/// Corrupt memory may be accepted as valid. While index bounds are validated,
-/// the data content is not. Treat recovered logs as untrusted external input.
+/// Corrupt memory may be accepted as valid. While the header checksum and index bounds are
+/// validated, the data content is not. Treat recovered logs as untrusted external input.
 pub fn init() -> Result<ConsumerAndMetadata<'static>, InitError> {
+    init_with_reset_reason(ResetReason::Unknown)
+}
+
+/// Initialize the logger and record why the MCU was reset.
+///
+/// Behaves like [`init`], but stores `reset_reason` in the persisted header and logs it in the
+/// session marker frame. Pass the reason read from the chip's reset status register, which
+/// tells why the run that produced the recovered logs ended. It is returned in
+/// [`ConsumerAndMetadata::previous_reset_reason`] along with those logs.
+///
+/// # Errors
+///
+/// See [`init`].
+pub fn init_with_reset_reason(
+    reset_reason: ResetReason,
+) -> Result<ConsumerAndMetadata<'static>, InitError> {
+    // SAFETY: The linker script reserves this region for the persist buffer.
+    unsafe { init_memory(linker_region(), reset_reason, None) }
+}
+
+/// Initialize the logger and move the recovered logs into `snapshot`.
+///
+/// Behaves like [`init_with_reset_reason`], but copies the recovered frames into `snapshot`
+/// and clears them from the buffer, before anything is logged by this run. New logs then
+/// cannot push out the crash report while it waits for a slow uplink, and the snapshot can be
+/// sent at its own pace. [`ConsumerAndMetadata::snapshot_len`] holds the number of bytes
+/// copied.
+///
+/// If the recovered frames do not fit, the oldest ones are discarded, as the last frames before
+/// a reset are usually the most interesting. Only complete frames are copied, so the snapshot
+/// can be decoded like the stream read from the [`Consumer`].
+///
+/// # Errors
+///
+/// See [`init`].
+pub fn init_with_snapshot(
+    reset_reason: ResetReason,
+    snapshot: &mut [u8],
+) -> Result<ConsumerAndMetadata<'static>, InitError> {
+    // SAFETY: The linker script reserves this region for the persist buffer.
+    unsafe { init_memory(linker_region(), reset_reason, Some(snapshot)) }
+}
+
+/// Returns the persist region delimited by the linker symbols.
+fn linker_region() -> Range<usize> {
     // SAFETY: These symbols are provided by the linker script and point to a reserved memory region.
     unsafe extern "C" {
         static __defmt_persist_start: u8;
         static __defmt_persist_end: u8;
     }
 
+    let start = (&raw const __defmt_persist_start).expose_provenance();
+    let end = (&raw const __defmt_persist_end).expose_provenance();
+    start..end
+}
+
+/// Initialize the logger with a memory region chosen at runtime.
+///
+/// Behaves like [`init`], but uses `region` instead of the linker symbols
+/// `__defmt_persist_start` and `__defmt_persist_end`, which then do not need to be defined.
+/// Use [`init_with_region_and_reset_reason`] to record the reset reason.
+///
+/// The region must start at an address aligned like a `u128` (8 bytes on Cortex-M, or 4 bytes
+/// with the `compact-header` feature and without `ecc`), and should be placed in a section that
+/// is not initialized at startup, e.g. `.uninit` with `cortex-m-rt`, so its contents survive
+/// resets.
+///
+/// # Errors
+///
+/// See [`init`].
+///
+/// # Safety
+///
+/// The contents of `region` are interpreted as persisted state from a previous run, and are
+/// written by the logger until the program ends. Nothing outside of this crate may access
+/// `region`, including code outside the Rust abstract machine such as DMA, another core, or
+/// a bootloader that is still running. As with [`init`], it is fine for a bootloader to pass
+/// the same region, provided it terminates before the application starts.
+pub unsafe fn init_with_region(
+    region: &'static mut [MaybeUninit<u8>],
+) -> Result<ConsumerAndMetadata<'static>, InitError> {
+    // SAFETY: Upheld by the caller.
+    unsafe { init_with_region_and_reset_reason(region, ResetReason::Unknown) }
+}
+
+/// Initialize the logger with a memory region chosen at runtime and record why the MCU was
+/// reset.
+///
+/// Behaves like [`init_with_region`], but stores `reset_reason` like
+/// [`init_with_reset_reason`].
+///
+/// # Errors
+///
+/// See [`init`].
+///
+/// # Safety
+///
+/// See [`init_with_region`].
+pub unsafe fn init_with_region_and_reset_reason(
+    region: &'static mut [MaybeUninit<u8>],
+    reset_reason: ResetReason,
+) -> Result<ConsumerAndMetadata<'static>, InitError> {
+    let start = region.as_mut_ptr().expose_provenance();
+
+    // SAFETY: `region` is borrowed for `'static`, and the caller guarantees that nothing else
+    // accesses it.
+    unsafe { init_memory(start..start + region.len(), reset_reason, None) }
+}
+
+/// Validates `memory` and initializes the logger with it, moving the recovered logs into
+/// `snapshot` if given.
+///
+/// # Safety
+///
+/// `memory` must be reserved for the persist buffer for the rest of the program, see
+/// [`RingBuffer::recover_or_reinitialize`].
+unsafe fn init_memory(
+    memory: Range<usize>,
+    reset_reason: ResetReason,
+    snapshot: Option<&mut [u8]>,
+) -> Result<ConsumerAndMetadata<'static>, InitError> {
     static INITIALIZED: AtomicBool = AtomicBool::new(false);
 
     if INITIALIZED.swap(true, Ordering::SeqCst) {
         return Err(InitError::AlreadyInitialized);
     }
 
-    let start = (&raw const __defmt_persist_start).expose_provenance();
-    let end = (&raw const __defmt_persist_end).expose_provenance();
-    let memory = start..end;
-
     if !memory.start.is_multiple_of(align_of::<RingBuffer>()) {
         return Err(InitError::BadAlignment);
     }
-    if memory.len() <= size_of::<RingBuffer>() {
-        return Err(InitError::TooSmall);
-    }
-    let buf_len = memory.len() - size_of::<RingBuffer>();
-    if buf_len >= i32::MAX as usize / 4 {
-        return Err(InitError::TooLarge);
-    }
+    #[cfg(feature = "dcache")]
+    let cacheable = dcache::is_write_back(memory.clone());
+    #[cfg(feature = "crash-ring")]
+    let (memory, crash_memory) = {
+        // At least 8, so `host::split_crash_ring` finds the same split with `compact-header`.
+        let align = align_of::<RingBuffer>().max(8);
+        let split = memory.start + crash_ring_offset(memory.len(), CRASH_PERCENT, align);
+        (memory.start..split, split..memory.end)
+    };
+    check_len(&memory)?;
+    #[cfg(feature = "crash-ring")]
+    check_len(&crash_memory)?;
 
     // SAFETY:
-    // - Linker symbols provide the memory region.
+    // - The caller reserves the memory region.
     // - The atomic swap above guarantees this code runs exactly once, ensuring exclusive ownership.
     // - Alignment and size are validated above.
-    let (p, mut c) = unsafe { RingBuffer::recover_or_reinitialize(memory) };
-
-    // SAFETY: The atomic swap guarantees this is called only once.
-    unsafe { logger::LOGGER_STATE.initialize(p) };
+    let (p, mut c, recovery_status) = unsafe { RingBuffer::recover_or_reinitialize(memory) };
+    // SAFETY: As above. The crash ring starts at an aligned offset after the live ring.
+    #[cfg(feature = "crash-ring")]
+    let (crash_p, crash_consumer, crash_recovery_status) =
+        unsafe { RingBuffer::recover_or_reinitialize(crash_memory) };
 
+    // Done before the logger is live, so no logs from this run are included.
+    let snapshot_len = snapshot.map_or(0, |snapshot| c.drain_into(snapshot));
     let recovered_logs_len = {
         let grant = c.read();
         let (buf1, buf2) = grant.bufs();
         buf1.len() + buf2.len()
     };
 
+    let dropped_frames = c.dropped_frames();
+    let boot_count = c.boot_count();
+    let previous_reset_reason = record_reset_reason(&mut c, recovery_status, reset_reason);
+    let firmware_id = firmware_id();
+    let previous_firmware_id = c.swap_firmware_id(firmware_id);
+
+    // SAFETY: The atomic swap guarantees this is called only once.
+    unsafe {
+        logger::LOGGER_STATE.initialize(
+            p,
+            #[cfg(feature = "crash-ring")]
+            crash_p,
+        )
+    };
+
+    // Marks the start of this run in the stream. `println` is not subject to `DEFMT_LOG`.
+    defmt::println!(
+        "defmt-persist: boot {=u32}, reset reason: {}",
+        boot_count,
+        reset_reason
+    );
+
     Ok(ConsumerAndMetadata {
         consumer: c,
+        #[cfg(feature = "crash-ring")]
+        crash_consumer,
+        #[cfg(feature = "crash-ring")]
+        crash_recovery_status,
         recovered_logs_len,
+        snapshot_len,
+        recovery_status,
+        dropped_frames,
+        boot_count,
+        previous_reset_reason,
+        firmware_id,
+        previous_firmware_id,
+        #[cfg(feature = "dcache")]
+        cacheable,
     })
 }
+
+/// Stores `reset_reason` in the header, and returns it as the reason the run that produced the
+/// recovered logs ended, or [`ResetReason::Unknown`] if the buffer was reinitialized.
+fn record_reset_reason(
+    c: &mut Consumer<'_>,
+    recovery_status: RecoveryStatus,
+    reset_reason: ResetReason,
+) -> ResetReason {
+    c.set_reset_reason(reset_reason.to_bits());
+    if recovery_status == RecoveryStatus::Reinitialized {
+        ResetReason::Unknown
+    } else {
+        reset_reason
+    }
+}
+
+/// Checks that `memory` can hold the ring buffer header plus data.
+///
+/// With the `word-write` feature, the data must also start at a word boundary and hold whole
+/// words.
+fn check_len(memory: &Range<usize>) -> Result<(), InitError> {
+    if memory.len() <= size_of::<RingBuffer>() {
+        return Err(InitError::TooSmall);
+    }
+    let buf_len = memory.len() - size_of::<RingBuffer>();
+    if buf_len >= i32::MAX as usize / 4 {
+        return Err(InitError::TooLarge);
+    }
+    #[cfg(feature = "word-write")]
+    if !(memory.start + size_of::<RingBuffer>()).is_multiple_of(size_of::<ring_buffer::Word>())
+        || !buf_len.is_multiple_of(size_of::<ring_buffer::Word>())
+    {
+        return Err(InitError::BadAlignment);
+    }
+    Ok(())
+}
+
+// CRASH_PERCENT is generated by build.rs from the DEFMT_PERSIST_CRASH_PERCENT env var.
+#[cfg(feature = "crash-ring")]
+include!(concat!(env!("OUT_DIR"), "/crash_ring.rs"));
+
+/// Returns the offset of the crash ring in a persist region of `len` bytes.
+///
+/// The crash ring gets `percent` of the region, rounded down so that its header is aligned to
+/// `align`. The region start must be aligned to `align` as well.
+#[cfg(any(feature = "crash-ring", feature = "host"))]
+pub(crate) const fn crash_ring_offset(len: usize, percent: usize, align: usize) -> usize {
+    let crash_len = (len as u64 * percent as u64 / 100) as usize;
+    let offset = (len - crash_len).next_multiple_of(align);
+    if offset < len { offset } else { len }
+}
+
+/// Sets the minimum level of the frames sent to RTT.
+///
+/// This is independent of the level of the frames stored in the buffer, set with
+/// [`Consumer::set_log_level`], so e.g. debug logs can be watched live over RTT while only
+/// warnings take up persistent space. Unlike that level, this one is not persisted, and all
+/// levels are sent after a reset.
+#[cfg(feature = "rtt")]
+pub fn set_rtt_log_level(level: LogLevel) {
+    logger::RTT_LOG_LEVEL.store(level.to_bits(), Ordering::Relaxed);
+}
+
+/// Sends the frames logged from now on to the crash ring instead of the live ring.
+///
+/// Call this first thing in the panic or fault handler, so the crash report cannot be pushed
+/// out by regular logs. It cannot be undone, the program is expected to reset after a crash.
+#[cfg(feature = "crash-ring")]
+pub fn set_crashing() {
+    logger::CRASHING.store(true, Ordering::Relaxed);
+}
+
+/// Computes the identifier of the running firmware, see [`ConsumerAndMetadata::firmware_id`].
+#[cfg(feature = "firmware-id")]
+fn firmware_id() -> u32 {
+    // SAFETY: The linker script places both symbols around read-only data in the same
+    // section, so the range is readable and never written.
+    unsafe { crc32_range(firmware_id_range()) }
+}
+
+/// Computes the identifier of the running firmware, see [`ConsumerAndMetadata::firmware_id`].
+#[cfg(not(feature = "firmware-id"))]
+fn firmware_id() -> u32 {
+    0
+}
+
+/// Returns the bytes delimited by the linker symbols `__defmt_persist_firmware_id_start` and
+/// `__defmt_persist_firmware_id_end`.
+#[cfg(all(feature = "firmware-id", not(test)))]
+fn firmware_id_range() -> Range<*const u8> {
+    // SAFETY: These symbols are provided by the linker script and delimit the identifying bytes.
+    unsafe extern "C" {
+        static __defmt_persist_firmware_id_start: u8;
+        static __defmt_persist_firmware_id_end: u8;
+    }
+
+    (&raw const __defmt_persist_firmware_id_start)..(&raw const __defmt_persist_firmware_id_end)
+}
+
+/// Returns the bytes identifying the firmware.
+///
+/// Unit tests are linked without the linker symbols, so they are identified by these bytes.
+#[cfg(all(feature = "firmware-id", test))]
+fn firmware_id_range() -> Range<*const u8> {
+    static ID: [u8; 4] = *b"test";
+    ID.as_ptr_range()
+}
+
+/// Computes the CRC-32 of the bytes in `range`.
+///
+/// An inverted range, e.g. from linker symbols placed in the wrong order, counts as empty.
+///
+/// # Safety
+///
+/// `range` must be readable and not written during the call.
+#[cfg(feature = "firmware-id")]
+unsafe fn crc32_range(range: Range<*const u8>) -> u32 {
+    let len = range.end.addr().saturating_sub(range.start.addr());
+    // SAFETY: Upheld by the caller.
+    let bytes = unsafe { core::slice::from_raw_parts(range.start, len) };
+    crc::crc32(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Required by defmt to link the test binary, which has no `defmt.x` linker script.
+    defmt::timestamp!("");
+
+    /// Memory for `init_with_region`, standing in for a persist region.
+    #[repr(C, align(16))]
+    struct Region([MaybeUninit<u8>; 512]);
+
+    // The logger can only be initialized once per process, so this is the only test calling
+    // an `init` function.
+    #[test]
+    #[cfg_attr(
+        feature = "qemu-test",
+        ignore = "logs via semihosting, which needs QEMU"
+    )]
+    fn init_region() {
+        static mut REGION: Region = Region([MaybeUninit::new(0); 512]);
+        static mut OTHER: Region = Region([MaybeUninit::new(0); 512]);
+        let (region, other) = (&raw mut REGION, &raw mut OTHER);
+
+        // SAFETY: The regions are only accessed here, and this test runs once.
+        let metadata =
+            unsafe { init_with_region_and_reset_reason(&mut (*region).0, ResetReason::Software) };
+        let metadata = metadata.unwrap();
+        assert_eq!(metadata.recovery_status, RecoveryStatus::Reinitialized);
+        assert_eq!(metadata.recovered_logs_len, 0);
+        assert_eq!(metadata.boot_count, 0);
+        assert_eq!(metadata.firmware_id, firmware_id());
+
+        // The session marker was logged into the region.
+        assert!(!metadata.consumer.is_empty());
+
+        #[cfg(feature = "crash-ring")]
+        {
+            assert_eq!(
+                metadata.crash_recovery_status,
+                RecoveryStatus::Reinitialized
+            );
+            assert!(metadata.crash_consumer.is_empty());
+            set_crashing();
+            defmt::println!("crash");
+            assert!(!metadata.crash_consumer.is_empty());
+        }
+
+        // SAFETY: See above.
+        let again = unsafe { init_with_region(&mut (*other).0) };
+        assert_eq!(again.err(), Some(InitError::AlreadyInitialized));
+    }
+
+    #[test]
+    fn reset_reason_two_boots() {
+        let mut region = Region([MaybeUninit::new(0); 512]);
+        let start = region.0.as_mut_ptr().expose_provenance();
+        let boot = |reset_reason| {
+            // SAFETY: The region is aligned and larger than the header. The producer and
+            // consumer of each boot are dropped before the next one.
+            let (_p, mut c, status) =
+                unsafe { RingBuffer::recover_or_reinitialize(start..start + region.0.len()) };
+            record_reset_reason(&mut c, status, reset_reason)
+        };
+
+        // Nothing was recovered, so there is no previous run.
+        assert_eq!(boot(ResetReason::PowerOn), ResetReason::Unknown);
+        // The reason passed by this run tells why the previous one ended, not the one it stored.
+        assert_eq!(boot(ResetReason::Watchdog), ResetReason::Watchdog);
+        assert_eq!(boot(ResetReason::Software), ResetReason::Software);
+    }
+
+    #[test]
+    #[cfg(feature = "firmware-id")]
+    fn firmware_id_crc() {
+        let bytes = *b"123456789";
+        // SAFETY: `bytes` is only read.
+        assert_eq!(unsafe { crc32_range(bytes.as_ptr_range()) }, 0xcbf4_3926);
+        // SAFETY: As above.
+        assert_eq!(
+            unsafe { crc32_range(bytes[4..].as_ptr_range()) },
+            crc::crc32(b"56789")
+        );
+        // An empty or inverted range identifies no bytes.
+        let ptr = bytes.as_ptr();
+        // SAFETY: As above.
+        assert_eq!(unsafe { crc32_range(ptr..ptr) }, 0);
+        // SAFETY: As above.
+        assert_eq!(unsafe { crc32_range(ptr.wrapping_add(1)..ptr) }, 0);
+        assert_eq!(firmware_id(), crc::crc32(b"test"));
+    }
+
+    #[test]
+    #[cfg(any(feature = "crash-ring", feature = "host"))]
+    fn crash_ring_split() {
+        assert_eq!(crash_ring_offset(256, 25, 8), 192);
+        // Rounded so the crash ring header stays aligned.
+        assert_eq!(crash_ring_offset(100, 25, 8), 80);
+        assert_eq!(crash_ring_offset(100, 30, 16), 80);
+        // No room left for the crash ring.
+        assert_eq!(crash_ring_offset(10, 90, 16), 10);
+    }
+}
@@ -1,4 +1,13 @@
-use crate::ring_buffer::Producer;
+use crate::{
+    LogLevel,
+    ring_buffer::{DROPPED_MASK, Producer},
+    sink,
+};
+#[cfg(feature = "frame-crc")]
+use crate::{
+    crc::{CRC32_INIT, crc32_finish, crc32_update},
+    frame::trailer,
+};
 use core::{
     cell::UnsafeCell,
     mem::MaybeUninit,
@@ -8,7 +17,7 @@ use critical_section::RestoreState;
 use defmt::Encoder;
 
 #[cfg(feature = "rtt")]
-mod rtt;
+pub(crate) mod rtt;
 
 #[cfg(feature = "qemu-test")]
 mod semihosting;
@@ -16,13 +25,50 @@ mod semihosting;
 #[cfg(feature = "async-await")]
 pub(crate) static WAKER: crate::atomic_waker::AtomicWaker = crate::atomic_waker::AtomicWaker::new();
 
+/// Minimum level of the frames sent to RTT, see `LogLevel::to_bits`.
+#[cfg(feature = "rtt")]
+pub(crate) static RTT_LOG_LEVEL: core::sync::atomic::AtomicU32 =
+    core::sync::atomic::AtomicU32::new(0);
+
+/// Number of rings the logger writes to.
+#[cfg(all(feature = "rtt-replay", not(feature = "crash-ring")))]
+const RINGS: usize = 1;
+#[cfg(all(feature = "rtt-replay", feature = "crash-ring"))]
+const RINGS: usize = 2;
+
+/// Set by `set_crashing` to send the following frames to the crash ring.
+#[cfg(feature = "crash-ring")]
+pub(crate) static CRASHING: AtomicBool = AtomicBool::new(false);
+
 #[defmt::global_logger]
 struct Logger;
 
 pub(crate) struct LoggerState {
     producer: UnsafeCell<MaybeUninit<Producer<'static>>>,
+    /// Writes to the crash ring.
+    #[cfg(feature = "crash-ring")]
+    crash_producer: UnsafeCell<MaybeUninit<Producer<'static>>>,
+    /// Whether the current frame is written to the crash ring, latched when the frame starts.
+    #[cfg(feature = "crash-ring")]
+    crash_frame: UnsafeCell<bool>,
+    /// Set until the first bytes of the current frame, which hold its interned string index,
+    /// are written.
+    index_pending: UnsafeCell<bool>,
+    /// Set if the current frame is below the runtime log level, and not stored.
+    filtered: UnsafeCell<bool>,
+    /// Set if the current frame is below the RTT log level, and not sent to RTT.
+    #[cfg(feature = "rtt")]
+    rtt_filtered: UnsafeCell<bool>,
     cs_state: UnsafeCell<RestoreState>,
     encoder: UnsafeCell<Encoder>,
+    /// Value of the dropped frames counter when it was last reported in the stream.
+    reported_dropped: UnsafeCell<u32>,
+    /// CRC register over the encoded bytes of the current frame.
+    #[cfg(feature = "frame-crc")]
+    frame_crc: UnsafeCell<u32>,
+    /// Start and length of the recovered data not yet replayed to RTT, per ring.
+    #[cfg(feature = "rtt-replay")]
+    replay: UnsafeCell<[Option<(usize, usize)>; RINGS]>,
     initialized: AtomicBool,
     /// Reentrancy depth counter. 0 = not logging, 1 = logging (owner), 2+ = reentrant.
     /// Reentrant calls (from NMI, HardFault, or panic during logging) are silently dropped.
@@ -33,14 +79,186 @@ impl LoggerState {
     /// # Safety
     ///
     /// Must only be called once per program execution.
-    pub(crate) unsafe fn initialize(&self, p: Producer<'static>) {
+    pub(crate) unsafe fn initialize(
+        &self,
+        p: Producer<'static>,
+        #[cfg(feature = "crash-ring")] crash_p: Producer<'static>,
+    ) {
+        // Frames dropped in previous runs are reported through `ConsumerAndMetadata`.
+        // SAFETY: `reported_dropped` is only accessed after `initialized` is set, see below.
+        unsafe { self.reported_dropped.get().write(p.dropped_frames()) };
+        // Everything in the rings at this point was recovered from the previous run.
+        // SAFETY: `replay` is only accessed after `initialized` is set, see below.
+        #[cfg(feature = "rtt-replay")]
+        unsafe {
+            self.replay.get().write([
+                Some(p.unreleased()),
+                #[cfg(feature = "crash-ring")]
+                Some(crash_p.unreleased()),
+            ])
+        };
         // SAFETY: The caller guarantees this is called only once, so there is no data race
         // on the `producer` field. The `UnsafeCell` provides interior mutability.
         unsafe { self.producer.get().write(MaybeUninit::new(p)) };
+        // SAFETY: As above.
+        #[cfg(feature = "crash-ring")]
+        unsafe {
+            self.crash_producer.get().write(MaybeUninit::new(crash_p))
+        };
         // Release: ensures the write to `producer` is visible before `initialized` becomes true.
         self.initialized.store(true, Ordering::Release);
     }
 
+    /// Prepares the state for the next frame, e.g. which ring it is written to.
+    ///
+    /// # Safety
+    ///
+    /// Must be called from within a critical section, before the frame is written.
+    #[inline]
+    unsafe fn start_frame(&self) {
+        // SAFETY: The critical section (upheld by caller) ensures exclusive access.
+        unsafe { self.index_pending.get().write(true) };
+        // SAFETY: As above.
+        unsafe { self.filtered.get().write(false) };
+        // SAFETY: As above.
+        #[cfg(feature = "rtt")]
+        unsafe {
+            self.rtt_filtered.get().write(false)
+        };
+        // SAFETY: As above.
+        #[cfg(feature = "crash-ring")]
+        unsafe {
+            self.crash_frame
+                .get()
+                .write(CRASHING.load(Ordering::Relaxed))
+        };
+    }
+
+    /// Replays the data recovered in the rings to RTT, once a host is connected to read it.
+    ///
+    /// Recovered data the consumer releases before then is not replayed.
+    ///
+    /// # Safety
+    ///
+    /// Must be called from within a critical section, before each frame is written.
+    #[cfg(feature = "rtt-replay")]
+    unsafe fn replay(&self) {
+        // Acquire: synchronizes with the Release store in `initialize`, ensuring we see
+        // the fully initialized producers and `replay`.
+        if !self.initialized.load(Ordering::Acquire) {
+            return;
+        }
+        // SAFETY: The critical section (upheld by caller) ensures exclusive access.
+        let pending = unsafe { &mut *self.replay.get() };
+        let connected = rtt::host_is_connected();
+        let producers = [
+            &self.producer,
+            #[cfg(feature = "crash-ring")]
+            &self.crash_producer,
+        ];
+        for (producer, pending) in producers.into_iter().zip(pending) {
+            let Some((start, len)) = *pending else {
+                continue;
+            };
+            // SAFETY: The Acquire load ensures the producers are initialized. The critical
+            // section (upheld by caller) ensures they are not written concurrently.
+            let producer = unsafe { &*producer.get().cast::<Producer>() };
+            // Checked on every frame, as `unreleased_part` requires, until it is replayed.
+            let Some((first, second)) = producer.unreleased_part(start, len) else {
+                *pending = None;
+                continue;
+            };
+            if !connected {
+                continue;
+            }
+
+            // The stored CRC trailers are not part of the defmt stream.
+            #[cfg(feature = "frame-crc")]
+            for frame in crate::frame::VerifiedFrames::new(first, second) {
+                let (first, second) = frame.bufs();
+                // SAFETY: The caller guarantees we're in a critical section.
+                unsafe {
+                    rtt::write(first);
+                    rtt::write(second);
+                    rtt::write(&[0]);
+                }
+            }
+            // SAFETY: The caller guarantees we're in a critical section.
+            #[cfg(not(feature = "frame-crc"))]
+            unsafe {
+                rtt::write(first);
+                rtt::write(second);
+            }
+            *pending = None;
+        }
+    }
+
+    /// Decides which outputs the current frame goes to, from the first bytes written to it.
+    ///
+    /// `frame_level` looks up the level of a format string index, see [`frame_level`].
+    ///
+    /// # Safety
+    ///
+    /// Must be called from within a critical section, with the unencoded bytes of the frame.
+    #[inline]
+    unsafe fn filter(&self, bytes: &[u8], frame_level: impl FnOnce(u16) -> Option<LogLevel>) {
+        // SAFETY: The critical section (upheld by caller) ensures exclusive access.
+        if !unsafe { self.index_pending.get().replace(false) } {
+            return;
+        }
+        // The crash report goes everywhere in full.
+        // SAFETY: The critical section (upheld by caller) ensures exclusive access.
+        #[cfg(feature = "crash-ring")]
+        if unsafe { *self.crash_frame.get() } {
+            return;
+        }
+
+        // defmt starts every frame with the 16-bit index of its format string.
+        let [low, high, ..] = *bytes else {
+            return;
+        };
+        let Some(level) = frame_level(u16::from_le_bytes([low, high])) else {
+            return;
+        };
+
+        #[cfg(feature = "rtt")]
+        {
+            // Relaxed: the level is only a setting, no data is accessed based on it.
+            let min = LogLevel::from_bits(RTT_LOG_LEVEL.load(Ordering::Relaxed));
+            // SAFETY: The critical section (upheld by caller) ensures exclusive access.
+            unsafe { self.rtt_filtered.get().write(level < min) };
+        }
+
+        // Acquire: synchronizes with the Release store in `initialize`, ensuring we see
+        // the fully initialized `producer`.
+        if self.initialized.load(Ordering::Acquire) {
+            // SAFETY: The Acquire load ensures `producer` is initialized. The critical section
+            // (upheld by caller) ensures exclusive access.
+            let min = unsafe { self.producer() }.log_level();
+            // SAFETY: The critical section (upheld by caller) ensures exclusive access.
+            unsafe { self.filtered.get().write(level < min) };
+        }
+    }
+
+    /// Returns the producer of the ring the current frame is written to.
+    ///
+    /// # Safety
+    ///
+    /// `initialized` must have been observed as `true` with Acquire ordering, and this must be
+    /// called from within a critical section to prevent aliasing of the producers.
+    #[allow(clippy::mut_from_ref)]
+    #[inline]
+    unsafe fn producer(&self) -> &mut Producer<'static> {
+        // SAFETY: The critical section (upheld by caller) ensures exclusive access.
+        #[cfg(feature = "crash-ring")]
+        if unsafe { *self.crash_frame.get() } {
+            // SAFETY: The caller guarantees `crash_producer` is initialized and not aliased.
+            return unsafe { &mut *self.crash_producer.get().cast::<Producer>() };
+        }
+        // SAFETY: The caller guarantees `producer` is initialized and not aliased.
+        unsafe { &mut *self.producer.get().cast::<Producer>() }
+    }
+
     /// # Safety
     ///
     /// Must be called from within a critical section to prevent aliasing of `producer`.
@@ -49,28 +267,212 @@ impl LoggerState {
         // Acquire: synchronizes with the Release store in `initialize`, ensuring we see
         // the fully initialized `producer`.
         if self.initialized.load(Ordering::Acquire) {
+            // SAFETY: The critical section (upheld by caller) ensures exclusive access.
+            if unsafe { *self.filtered.get() } {
+                return;
+            }
+
+            // rzcobs only emits a zero as the final delimiter of a frame. It is held back and
+            // written after the CRC trailer in `commit`.
+            #[cfg(feature = "frame-crc")]
+            let bytes = {
+                let bytes = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+                // SAFETY: The critical section (upheld by caller) ensures exclusive access.
+                let crc = unsafe { &mut *self.frame_crc.get() };
+                *crc = crc32_update(*crc, bytes);
+                bytes
+            };
+
             // SAFETY: The Acquire load ensures `producer` is initialized. The critical section
             // (upheld by caller) ensures exclusive access, so creating `&mut` is safe.
-            unsafe { &mut *self.producer.get().cast::<Producer>() }.write(bytes);
+            unsafe { self.producer() }.write(bytes);
         }
     }
+
+    /// Makes the frame written since the last commit visible to the consumer.
+    ///
+    /// Returns the number of frames dropped since the last report if this frame was stored,
+    /// so the caller can report them now that there is space again.
+    ///
+    /// # Safety
+    ///
+    /// Must be called from within a critical section to prevent aliasing of `producer`.
+    #[inline]
+    unsafe fn commit(&self) -> Option<u32> {
+        // Acquire: synchronizes with the Release store in `initialize`, ensuring we see
+        // the fully initialized `producer`.
+        if !self.initialized.load(Ordering::Acquire) {
+            return None;
+        }
+        // SAFETY: The Acquire load ensures `producer` is initialized. The critical section
+        // (upheld by caller) ensures exclusive access, so creating `&mut` is safe.
+        let producer = unsafe { self.producer() };
+
+        // Nothing of a filtered frame was written, apart from a separator written before the
+        // level was known. Dropping it keeps it, or the discard it may have caused when the
+        // ring was full, from carrying over into the next frame.
+        // SAFETY: The critical section (upheld by caller) ensures exclusive access.
+        if unsafe { *self.filtered.get() } {
+            producer.abort();
+            return None;
+        }
+
+        #[cfg(feature = "frame-crc")]
+        {
+            // SAFETY: The critical section (upheld by caller) ensures exclusive access.
+            let crc = unsafe { &mut *self.frame_crc.get() };
+            producer.write(&trailer(crc32_finish(*crc)));
+            producer.write(&[0]);
+            *crc = CRC32_INIT;
+        }
+
+        if !producer.commit() {
+            return None;
+        }
+
+        // Drops from the crash ring are only counted, reports would take up its space.
+        // SAFETY: The critical section (upheld by caller) ensures exclusive access.
+        #[cfg(feature = "crash-ring")]
+        if unsafe { *self.crash_frame.get() } {
+            return None;
+        }
+
+        // SAFETY: The critical section (upheld by caller) ensures exclusive access.
+        let reported = unsafe { &mut *self.reported_dropped.get() };
+        let dropped = producer.dropped_frames();
+        // The counter wraps at its width, which is only 5 bits with `compact-header`.
+        let unreported = dropped.wrapping_sub(*reported) & DROPPED_MASK;
+        *reported = dropped;
+        (unreported != 0).then_some(unreported)
+    }
 }
 
 // SAFETY: All mutable access to fields is protected by either:
 // - `initialized` flag with Acquire/Release ordering (for `producer`).
-// - Critical sections (for `cs_state`, `encoder`, and `producer` during writes).
+// - Critical sections (for `cs_state`, `encoder`, `reported_dropped`, `frame_crc`,
+//   `crash_frame`, `index_pending`, `filtered`, `rtt_filtered`, `replay`, and the producers
+//   during writes).
 // The `initialized` flag uses atomic operations for thread-safe access.
 unsafe impl Sync for LoggerState {}
 
-pub(crate) static LOGGER_STATE: LoggerState = LoggerState {
-    producer: UnsafeCell::new(MaybeUninit::uninit()),
-    cs_state: UnsafeCell::new(RestoreState::invalid()),
-    encoder: UnsafeCell::new(Encoder::new()),
-    initialized: AtomicBool::new(false),
-    depth: AtomicUsize::new(0),
-};
+pub(crate) static LOGGER_STATE: LoggerState = LoggerState::new();
+
+impl LoggerState {
+    const fn new() -> Self {
+        LoggerState {
+            producer: UnsafeCell::new(MaybeUninit::uninit()),
+            #[cfg(feature = "crash-ring")]
+            crash_producer: UnsafeCell::new(MaybeUninit::uninit()),
+            #[cfg(feature = "crash-ring")]
+            crash_frame: UnsafeCell::new(false),
+            index_pending: UnsafeCell::new(false),
+            filtered: UnsafeCell::new(false),
+            #[cfg(feature = "rtt")]
+            rtt_filtered: UnsafeCell::new(false),
+            cs_state: UnsafeCell::new(RestoreState::invalid()),
+            encoder: UnsafeCell::new(Encoder::new()),
+            reported_dropped: UnsafeCell::new(0),
+            #[cfg(feature = "frame-crc")]
+            frame_crc: UnsafeCell::new(CRC32_INIT),
+            #[cfg(feature = "rtt-replay")]
+            replay: UnsafeCell::new([None; RINGS]),
+            initialized: AtomicBool::new(false),
+            depth: AtomicUsize::new(0),
+        }
+    }
+}
+
+/// Calls `f` with the data in the rings and the boot counter, in a critical section.
+///
+/// Each ring is passed as its two parts, like `GrantR::bufs`. With the `crash-ring` feature, the
+/// crash ring follows the live ring. Returns `None` if the logger is not initialized.
+#[cfg(feature = "flash")]
+pub(crate) fn with_rings<R>(f: impl FnOnce(&[(&[u8], &[u8])], u32) -> R) -> Option<R> {
+    critical_section::with(|_| {
+        // Acquire: synchronizes with the Release store in `initialize`, ensuring we see
+        // the fully initialized producers.
+        if !LOGGER_STATE.initialized.load(Ordering::Acquire) {
+            return None;
+        }
+        let ring = |producer: &UnsafeCell<MaybeUninit<Producer<'static>>>| {
+            // SAFETY: The Acquire load ensures the producers are initialized. The critical
+            // section ensures they are not written concurrently.
+            let producer = unsafe { &*producer.get().cast::<Producer>() };
+            let (start, len) = producer.unreleased();
+            (
+                producer,
+                producer.unreleased_part(start, len).unwrap_or_default(),
+            )
+        };
+        let (producer, live) = ring(&LOGGER_STATE.producer);
+        let rings = [
+            live,
+            #[cfg(feature = "crash-ring")]
+            ring(&LOGGER_STATE.crash_producer).1,
+        ];
+        Some(f(&rings, producer.boot_count()))
+    })
+}
+
+/// Returns the level of the frame with the format string `index`, or `None` for frames without
+/// a level, e.g. from `println`.
+///
+/// `defmt.x` orders the format strings by level and places markers in between.
+#[cfg(not(test))]
+fn frame_level(index: u16) -> Option<LogLevel> {
+    // SAFETY: These symbols are provided by `defmt.x`. Only their addresses are used.
+    unsafe extern "C" {
+        static __DEFMT_MARKER_TRACE_START: u8;
+        static __DEFMT_MARKER_DEBUG_START: u8;
+        static __DEFMT_MARKER_INFO_START: u8;
+        static __DEFMT_MARKER_WARN_START: u8;
+        static __DEFMT_MARKER_ERROR_START: u8;
+        static __DEFMT_MARKER_ERROR_END: u8;
+    }
+
+    let index = usize::from(index);
+    let levels = [
+        (
+            (&raw const __DEFMT_MARKER_TRACE_START).addr(),
+            LogLevel::Trace,
+        ),
+        (
+            (&raw const __DEFMT_MARKER_DEBUG_START).addr(),
+            LogLevel::Debug,
+        ),
+        (
+            (&raw const __DEFMT_MARKER_INFO_START).addr(),
+            LogLevel::Info,
+        ),
+        (
+            (&raw const __DEFMT_MARKER_WARN_START).addr(),
+            LogLevel::Warn,
+        ),
+        (
+            (&raw const __DEFMT_MARKER_ERROR_START).addr(),
+            LogLevel::Error,
+        ),
+    ];
+    if index < levels[0].0 || index >= (&raw const __DEFMT_MARKER_ERROR_END).addr() {
+        return None;
+    }
+    levels
+        .iter()
+        .rev()
+        .find(|(start, _)| index >= *start)
+        .map(|&(_, level)| level)
+}
 
-/// Writes data to all configured outputs (ring buffer, RTT, and semihosting).
+/// Returns the level of the frame with the format string `index`.
+///
+/// Unit tests are linked without `defmt.x`, so the logger treats all frames as having no level
+/// there. The tests of `LoggerState::filter` pass their own lookup.
+#[cfg(test)]
+fn frame_level(_index: u16) -> Option<LogLevel> {
+    None
+}
+
+/// Writes data to all configured outputs (ring buffer, RTT, semihosting, and sinks).
 ///
 /// # Safety
 ///
@@ -79,16 +481,20 @@ pub(crate) static LOGGER_STATE: LoggerState = LoggerState {
 unsafe fn write_all(data: &[u8]) {
     // SAFETY: Caller guarantees we're in a critical section.
     unsafe { LOGGER_STATE.write(data) };
+    // SAFETY: Caller guarantees we're in a critical section, so exclusive access to
+    // `rtt_filtered` is guaranteed.
     #[cfg(feature = "rtt")]
-    // SAFETY: Caller guarantees we're in a critical section.
-    unsafe {
-        rtt::write(data)
-    };
+    if !unsafe { *LOGGER_STATE.rtt_filtered.get() } {
+        // SAFETY: Caller guarantees we're in a critical section.
+        unsafe { rtt::write(data) };
+    }
     #[cfg(feature = "qemu-test")]
     // SAFETY: Caller guarantees we're in a critical section.
     unsafe {
         semihosting::write(data)
     };
+    // SAFETY: Caller guarantees we're in a critical section.
+    unsafe { sink::write(data) };
 }
 
 // SAFETY: This impl upholds the `defmt::Logger` safety contract:
@@ -120,21 +526,30 @@ unsafe impl defmt::Logger for Logger {
 
         compiler_fence(Ordering::SeqCst);
 
+        // SAFETY: We're in a critical section, before the frame is written.
+        unsafe { LOGGER_STATE.start_frame() };
+
+        // SAFETY: We're in a critical section, before the frame is written.
+        #[cfg(feature = "rtt-replay")]
+        unsafe {
+            LOGGER_STATE.replay()
+        };
+
         // SAFETY: We're in a critical section, so exclusive access to `encoder` is guaranteed.
         // The callback to `write_all` is also within the critical section.
         unsafe { &mut *LOGGER_STATE.encoder.get() }.start_frame(|b| unsafe { write_all(b) });
     }
 
     unsafe fn flush() {
-        if LOGGER_STATE.depth.load(Ordering::Relaxed) != 1 {
-            return;
+        if LOGGER_STATE.depth.load(Ordering::Relaxed) == 1 {
+            // SAFETY: Caller guarantees we're between acquire() and release().
+            #[cfg(feature = "rtt")]
+            unsafe {
+                rtt::flush()
+            };
+            // SAFETY: Caller guarantees we're between acquire() and release().
+            unsafe { sink::flush() };
         }
-
-        #[cfg(feature = "rtt")]
-        // SAFETY: Caller guarantees we're between acquire() and release().
-        unsafe {
-            rtt::flush()
-        };
     }
 
     unsafe fn release() {
@@ -147,6 +562,10 @@ unsafe impl defmt::Logger for Logger {
         // Exclusive access to `encoder` is guaranteed.
         unsafe { &mut *LOGGER_STATE.encoder.get() }.end_frame(|b| unsafe { write_all(b) });
 
+        // SAFETY: We're still in the critical section from `acquire()`.
+        // The frame is complete, publish it to the consumer as a whole.
+        let dropped = unsafe { LOGGER_STATE.commit() };
+
         compiler_fence(Ordering::SeqCst);
 
         // SAFETY: We read the restore state that was saved in `acquire()` and release
@@ -157,6 +576,14 @@ unsafe impl defmt::Logger for Logger {
 
         #[cfg(feature = "async-await")]
         WAKER.wake();
+
+        // Logged as a frame of its own, now that the previous frame shows there is space again.
+        // If this frame is dropped too, it is counted and reported with the next stored frame.
+        // Without a level, so neither `DEFMT_LOG` nor the runtime log level filters it out
+        // after the count was marked as reported.
+        if let Some(dropped) = dropped {
+            defmt::println!("defmt-persist: {=u32} messages dropped", dropped);
+        }
     }
 
     unsafe fn write(bytes: &[u8]) {
@@ -164,9 +591,99 @@ unsafe impl defmt::Logger for Logger {
             return;
         }
 
+        // SAFETY: Caller (defmt) guarantees this is called between acquire() and release(),
+        // so we're within a critical section.
+        unsafe { LOGGER_STATE.filter(bytes, frame_level) };
+
         // SAFETY: Caller (defmt) guarantees this is called between acquire() and release(),
         // so we're within a critical section. The encoder encodes the bytes and calls
         // our callback with the encoded data.
         unsafe { &mut *LOGGER_STATE.encoder.get() }.write(bytes, |b| unsafe { write_all(b) });
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ring_buffer::{Consumer, RingBuffer};
+
+    /// Memory for `recover_or_reinitialize`, standing in for a persist region.
+    #[repr(C, align(16))]
+    struct Region([u8; 256]);
+
+    fn recover(region: &mut Region) -> (Producer<'static>, Consumer<'static>) {
+        let start = region.0.as_mut_ptr().expose_provenance();
+        // SAFETY: The region is aligned and larger than the header. The tests drop the returned
+        // producer and consumer before accessing `region` again.
+        let (p, c, _) =
+            unsafe { RingBuffer::recover_or_reinitialize(start..start + region.0.len()) };
+        (p, c)
+    }
+
+    /// Writes a frame with the format string `index` through `state`, as `Logger` does.
+    ///
+    /// The frame is not encoded, so `index` must not contain a zero byte.
+    fn log(state: &LoggerState, index: u16, level: Option<LogLevel>) {
+        let [low, high] = index.to_le_bytes();
+        critical_section::with(|_| {
+            // SAFETY: In a critical section.
+            unsafe {
+                state.start_frame();
+                state.filter(&[low, high], |i| {
+                    assert_eq!(i, index);
+                    level
+                });
+                state.write(&[low, high, 7]);
+                // Later writes don't hold the index.
+                state.filter(&[0, 0], |_| Some(LogLevel::Error));
+                state.write(&[0]);
+                assert_eq!(state.commit(), None);
+            }
+        })
+    }
+
+    #[test]
+    fn filter_log_level() {
+        let mut region = Region([0; 256]);
+        #[cfg(feature = "crash-ring")]
+        let mut crash_region = Region([0; 256]);
+        let (p, mut c) = recover(&mut region);
+        c.set_log_level(LogLevel::Warn);
+        let state = LoggerState::new();
+        // SAFETY: Called once for this state.
+        unsafe {
+            state.initialize(
+                p,
+                #[cfg(feature = "crash-ring")]
+                recover(&mut crash_region).0,
+            )
+        };
+
+        // The separator the encoder writes before its first frame, which is filtered.
+        critical_section::with(|_| {
+            // SAFETY: In a critical section.
+            unsafe {
+                state.start_frame();
+                state.write(&[0]);
+                state.filter(&[1, 1], |_| Some(LogLevel::Info));
+                assert_eq!(state.commit(), None);
+            }
+        });
+        assert!(c.is_empty());
+
+        log(&state, 0x0202, Some(LogLevel::Debug));
+        assert!(c.is_empty());
+        log(&state, 0x0303, Some(LogLevel::Error));
+        log(&state, 0x0404, Some(LogLevel::Warn));
+        log(&state, 0x0505, None);
+
+        // The separator of the filtered frame was dropped, not committed with the next frame.
+        let r = c.read();
+        assert_eq!(r.bufs().0[0], 3);
+        let mut frames = r.frames();
+        for index in [3, 4, 5] {
+            assert_eq!(frames.next().unwrap().bufs().0[..3], [index, index, 7]);
+        }
+        assert!(frames.next().is_none());
+    }
+}
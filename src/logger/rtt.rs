@@ -2,15 +2,21 @@
 //!
 //! Based on defmt-rtt. The host/debugger can set MODE_BLOCK_IF_FULL in the channel flags
 //! to enable blocking mode when connected.
+//!
+//! Up channel 0 carries the defmt frames. Extra up channels for plain text and down channels
+//! for host commands can be configured at build time, and are handed out by [`RttUpChannel`]
+//! and [`RttDownChannel`].
 
 use core::{
     cell::UnsafeCell,
+    fmt,
     mem::MaybeUninit,
     ptr,
-    sync::atomic::{AtomicU32, Ordering},
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
 };
 
-// BUF_SIZE is generated by build.rs from the DEFMT_RTT_BUFFER_SIZE env var.
+// BUF_SIZE, EXTRA_UP_CHANNELS, UP_BUF_SIZE, DOWN_CHANNELS and DOWN_BUF_SIZE are generated by
+// build.rs from the DEFMT_RTT_BUFFER_SIZE and DEFMT_PERSIST_RTT_* env vars.
 include!(concat!(env!("OUT_DIR"), "/consts.rs"));
 
 /// Writes bytes to the RTT up channel.
@@ -39,6 +45,13 @@ pub(crate) unsafe fn flush() {
     unsafe { &*_SEGGER_RTT.up_channel.get() }.flush()
 }
 
+/// Returns `true` if a host has put the up channel in blocking mode, i.e. is reading it.
+#[cfg(feature = "rtt-replay")]
+pub(crate) fn host_is_connected() -> bool {
+    // SAFETY: Only the atomic `flags` field is accessed, which the host may write at any time.
+    unsafe { &*_SEGGER_RTT.up_channel.get() }.host_is_connected()
+}
+
 /// Mask for MODE bits.
 const MODE_MASK: u32 = 0b11;
 /// Block the application if the RTT buffer is full, wait for the host to read data.
@@ -51,20 +64,57 @@ const MODE_NON_BLOCKING_TRIM: u32 = 1;
 // So we declare the RTT control block here and make it impossible to use `rtt-target` together
 // with this crate.
 #[unsafe(no_mangle)]
-static _SEGGER_RTT: RttHeader = RttHeader::new(NAME.as_ptr(), BUFFER.0.get().cast());
+static _SEGGER_RTT: RttHeader = RttHeader::new(
+    NAME.as_ptr(),
+    BUFFER.0.get().cast(),
+    UP_BUFFERS.0.get().cast(),
+    DOWN_BUFFERS.0.get().cast(),
+);
+
+#[cfg_attr(target_os = "macos", unsafe(link_section = ".uninit,defmt-rtt.BUFFER"))]
+#[cfg_attr(
+    not(target_os = "macos"),
+    unsafe(link_section = ".uninit.defmt-rtt.BUFFER")
+)]
+static BUFFER: UnsafeBuffer<BUF_SIZE> =
+    UnsafeBuffer(UnsafeCell::new([MaybeUninit::uninit(); BUF_SIZE]));
 
+/// Buffers of the extra up channels, back to back.
 #[cfg_attr(target_os = "macos", unsafe(link_section = ".uninit,defmt-rtt.BUFFER"))]
 #[cfg_attr(
     not(target_os = "macos"),
     unsafe(link_section = ".uninit.defmt-rtt.BUFFER")
 )]
-static BUFFER: UnsafeBuffer = UnsafeBuffer(UnsafeCell::new([MaybeUninit::uninit(); BUF_SIZE]));
+static UP_BUFFERS: UnsafeBuffer<{ EXTRA_UP_CHANNELS * UP_BUF_SIZE }> = UnsafeBuffer(
+    UnsafeCell::new([MaybeUninit::uninit(); EXTRA_UP_CHANNELS * UP_BUF_SIZE]),
+);
+
+/// Buffers of the down channels, back to back.
+#[cfg_attr(target_os = "macos", unsafe(link_section = ".uninit,defmt-rtt.BUFFER"))]
+#[cfg_attr(
+    not(target_os = "macos"),
+    unsafe(link_section = ".uninit.defmt-rtt.BUFFER")
+)]
+static DOWN_BUFFERS: UnsafeBuffer<{ DOWN_CHANNELS * DOWN_BUF_SIZE }> = UnsafeBuffer(
+    UnsafeCell::new([MaybeUninit::uninit(); DOWN_CHANNELS * DOWN_BUF_SIZE]),
+);
 
 // Place NAME in data section, so the whole RTT header can be read from RAM.
 // This is useful if flash access gets disabled by the firmware at runtime.
 #[unsafe(link_section = ".data")]
 static NAME: [u8; 6] = *b"defmt\0";
 
+/// Name of the extra up channels and the down channels, as used by `rtt-target`.
+#[unsafe(link_section = ".data")]
+static TERMINAL_NAME: [u8; 9] = *b"Terminal\0";
+
+/// Set once an extra up channel has been handed out by [`RttUpChannel::take`].
+static UP_TAKEN: [AtomicBool; EXTRA_UP_CHANNELS] =
+    [const { AtomicBool::new(false) }; EXTRA_UP_CHANNELS];
+
+/// Set once a down channel has been handed out by [`RttDownChannel::take`].
+static DOWN_TAKEN: [AtomicBool; DOWN_CHANNELS] = [const { AtomicBool::new(false) }; DOWN_CHANNELS];
+
 #[repr(C)]
 struct RttHeader {
     /// RTT pattern.
@@ -75,22 +125,59 @@ struct RttHeader {
     max_down_channels: u32,
     /// Data buffer.
     up_channel: UnsafeCell<Channel>,
+    /// Up channels 1 and above, for plain text.
+    extra_up_channels: [UnsafeCell<Channel>; EXTRA_UP_CHANNELS],
+    /// Down channels, for host commands.
+    down_channels: [UnsafeCell<Channel>; DOWN_CHANNELS],
 }
 
 impl RttHeader {
-    const fn new(name: *const u8, buffer: *mut MaybeUninit<u8>) -> Self {
+    const fn new(
+        name: *const u8,
+        buffer: *mut MaybeUninit<u8>,
+        up_buffers: *mut MaybeUninit<u8>,
+        down_buffers: *mut MaybeUninit<u8>,
+    ) -> Self {
+        let mut extra_up_channels =
+            [const { UnsafeCell::new(Channel::new(ptr::null(), ptr::null_mut(), 0, 0)) };
+                EXTRA_UP_CHANNELS];
+        let mut i = 0;
+        while i < extra_up_channels.len() {
+            extra_up_channels[i] = UnsafeCell::new(Channel::new(
+                TERMINAL_NAME.as_ptr(),
+                up_buffers.wrapping_add(i * UP_BUF_SIZE),
+                UP_BUF_SIZE,
+                MODE_NON_BLOCKING_TRIM,
+            ));
+            i += 1;
+        }
+
+        let mut down_channels =
+            [const { UnsafeCell::new(Channel::new(ptr::null(), ptr::null_mut(), 0, 0)) };
+                DOWN_CHANNELS];
+        let mut i = 0;
+        while i < down_channels.len() {
+            down_channels[i] = UnsafeCell::new(Channel::new(
+                TERMINAL_NAME.as_ptr(),
+                down_buffers.wrapping_add(i * DOWN_BUF_SIZE),
+                DOWN_BUF_SIZE,
+                0,
+            ));
+            i += 1;
+        }
+
         RttHeader {
             id: *b"SEGGER RTT\0\0\0\0\0\0", // Defined by SEGGER
-            max_up_channels: 1,
-            max_down_channels: 0,
-            up_channel: UnsafeCell::new(Channel {
+            max_up_channels: 1 + EXTRA_UP_CHANNELS as u32,
+            max_down_channels: DOWN_CHANNELS as u32,
+            up_channel: UnsafeCell::new(Channel::new(
                 name,
                 buffer,
-                size: BUF_SIZE as u32,
-                write: AtomicU32::new(0),
-                read: AtomicU32::new(0),
-                flags: AtomicU32::new(MODE_NON_BLOCKING_TRIM),
-            }),
+                BUF_SIZE,
+                MODE_NON_BLOCKING_TRIM,
+            )),
+            extra_up_channels,
+            down_channels,
         }
     }
 }
@@ -99,29 +186,31 @@ impl RttHeader {
 // - The `id`, `max_up_channels`, and `max_down_channels` fields are immutable after construction.
 // - The `up_channel` is protected by `UnsafeCell` and only accessed via `write()` and `flush()`,
 //   which require the caller to be in a critical section.
+// - Each of the `extra_up_channels` and `down_channels` is only accessed through the single
+//   `RttUpChannel` or `RttDownChannel` handed out for it.
 // - The RTT protocol itself handles concurrent access from the host (debugger) via atomic
 //   read/write pointers with appropriate memory ordering.
 unsafe impl Sync for RttHeader {}
 
 #[repr(transparent)]
-struct UnsafeBuffer(UnsafeCell<[MaybeUninit<u8>; BUF_SIZE]>);
+struct UnsafeBuffer<const N: usize>(UnsafeCell<[MaybeUninit<u8>; N]>);
 
 // SAFETY: UnsafeBuffer can be safely shared between threads because:
 // - It is only accessed through the Channel's buffer pointer within critical sections.
 // - The RTT protocol uses atomic read/write pointers to coordinate access between
 //   the target (writer) and host (reader), preventing data races.
-unsafe impl Sync for UnsafeBuffer {}
+unsafe impl<const N: usize> Sync for UnsafeBuffer<N> {}
 
-/// RTT Up channel
+/// RTT channel, used for both directions.
 #[repr(C)]
 struct Channel {
     name: *const u8,
     /// Pointer to the RTT buffer.
     buffer: *mut MaybeUninit<u8>,
     size: u32,
-    /// Written by the target.
+    /// Written by the target for up channels, and by the host for down channels.
     write: AtomicU32,
-    /// Written by the host.
+    /// Written by the host for up channels, and by the target for down channels.
     read: AtomicU32,
     /// Channel properties.
     ///
@@ -130,6 +219,17 @@ struct Channel {
 }
 
 impl Channel {
+    const fn new(name: *const u8, buffer: *mut MaybeUninit<u8>, size: usize, flags: u32) -> Self {
+        Channel {
+            name,
+            buffer,
+            size: size as u32,
+            write: AtomicU32::new(0),
+            read: AtomicU32::new(0),
+            flags: AtomicU32::new(flags),
+        }
+    }
+
     fn write_all(&self, mut bytes: &[u8]) {
         // The host-connection-status is only modified after RAM initialization while the device is
         // halted, so we only need to check it once before the write-loop.
@@ -158,7 +258,7 @@ impl Channel {
 
         let read = self.read.load(Ordering::Relaxed) as usize;
         let write = self.write.load(Ordering::Acquire) as usize;
-        let available = available_buffer_size(read, write);
+        let available = available_buffer_size(read, write, self.size as usize);
 
         if available == 0 {
             return 0;
@@ -171,26 +271,27 @@ impl Channel {
         let read = self.read.load(Ordering::Relaxed) as usize;
         let write = self.write.load(Ordering::Acquire) as usize;
 
-        let available = available_buffer_size(read, write);
+        let available = available_buffer_size(read, write, self.size as usize);
         self.write_impl(bytes, write, available)
     }
 
     // Ring buffer copy safety invariants (referenced by SAFETY comments below):
-    // - `buf` points to a valid buffer of size `BUF_SIZE` (initialized in `_SEGGER_RTT`).
-    // - `cursor` is always < BUF_SIZE (maintained by `% BUF_SIZE` in write pointer updates).
+    // - `buf` points to a valid buffer of `size` bytes (initialized in `_SEGGER_RTT`).
+    // - `cursor` is always < size (maintained by `% size` in write pointer updates).
     // - `len <= available` ensures we don't write past the read pointer (ring buffer invariant).
-    // - `available_buffer_size` always leaves 1 byte gap, so `len < BUF_SIZE`.
+    // - `available_buffer_size` always leaves 1 byte gap, so `len < size`.
     // - Source (`bytes`) is valid for `len` bytes by slice invariants.
     // - No overlap: `bytes` is caller-provided stack/heap data, `buf` is the static RTT buffer.
     fn write_impl(&self, bytes: &[u8], cursor: usize, available: usize) -> usize {
         let len = bytes.len().min(available);
         let buf: *mut u8 = self.buffer.cast();
+        let size = self.size as usize;
 
-        if cursor + len > BUF_SIZE {
+        if cursor + len > size {
             // Wrapping case: split into two copies.
-            let pivot = BUF_SIZE - cursor;
+            let pivot = size - cursor;
             // SAFETY: See ring buffer copy safety invariants above.
-            // First copy: bytes[0..pivot] -> buf[cursor..BUF_SIZE]
+            // First copy: bytes[0..pivot] -> buf[cursor..size]
             unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), buf.add(cursor), pivot) };
             // SAFETY: See ring buffer copy safety invariants above.
             // Second copy: bytes[pivot..len] -> buf[0..len-pivot]
@@ -200,14 +301,44 @@ impl Channel {
             unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), buf.add(cursor), len) };
         }
 
-        self.write.store(
-            (cursor.wrapping_add(len) % BUF_SIZE) as u32,
-            Ordering::Release,
-        );
+        self.write
+            .store((cursor.wrapping_add(len) % size) as u32, Ordering::Release);
 
         len
     }
 
+    /// Reads the bytes written by the host into `bytes`, returning how many were read.
+    fn read(&self, bytes: &mut [u8]) -> usize {
+        // Acquire: synchronizes with the host's write, ensuring we see the written data.
+        let write = self.write.load(Ordering::Acquire) as usize;
+        let read = self.read.load(Ordering::Relaxed) as usize;
+        let size = self.size as usize;
+        // The host may write anything, don't trust it for the bounds of our copies.
+        if write >= size || read >= size {
+            return 0;
+        }
+
+        let buf: *const u8 = self.buffer.cast();
+        let mut cursor = read;
+        let mut total = 0;
+        while cursor != write && total < bytes.len() {
+            let end = if write > cursor { write } else { size };
+            let len = (end - cursor).min(bytes.len() - total);
+            // SAFETY: `cursor + len <= size`, so the source is inside the channel buffer, which
+            // the host does not write between `read` and `write`. The destination is a
+            // separate slice with room for `len` bytes.
+            unsafe {
+                ptr::copy_nonoverlapping(buf.add(cursor), bytes.as_mut_ptr().add(total), len)
+            };
+            cursor = (cursor + len) % size;
+            total += len;
+        }
+
+        // Release: the host may reuse the space once it sees the new read pointer.
+        self.read.store(cursor as u32, Ordering::Release);
+        total
+    }
+
     fn flush(&self) {
         if !self.host_is_connected() {
             return;
@@ -226,10 +357,110 @@ impl Channel {
 }
 
 /// How much space is left in the buffer?
-fn available_buffer_size(read_cursor: usize, write_cursor: usize) -> usize {
+fn available_buffer_size(read_cursor: usize, write_cursor: usize, size: usize) -> usize {
     if read_cursor > write_cursor {
         read_cursor - write_cursor - 1
     } else {
-        BUF_SIZE - write_cursor - 1 + read_cursor
+        size - write_cursor - 1 + read_cursor
+    }
+}
+
+/// An extra RTT up channel for plain text, e.g. a terminal next to the defmt logs.
+///
+/// The number of extra up channels is set at build time with the
+/// `DEFMT_PERSIST_RTT_UP_CHANNELS` environment variable (default: 0), and their buffer size with
+/// `DEFMT_PERSIST_RTT_UP_BUFFER_SIZE` (default: 256). Up channel 0 is reserved for defmt.
+pub struct RttUpChannel {
+    channel: &'static Channel,
+}
+
+impl RttUpChannel {
+    /// Takes up channel `number`, counting from 1.
+    ///
+    /// Returns `None` if the channel does not exist or has already been taken.
+    pub fn take(number: usize) -> Option<Self> {
+        let index = number.checked_sub(1)?;
+        if UP_TAKEN.get(index)?.swap(true, Ordering::Relaxed) {
+            return None;
+        }
+        // SAFETY: The channel is only accessed through this handle, which can be taken once.
+        let channel = unsafe { &*_SEGGER_RTT.extra_up_channels[index].get() };
+        Some(RttUpChannel { channel })
+    }
+
+    /// Writes `bytes` to the channel.
+    ///
+    /// Like the defmt channel, this blocks while the buffer is full if the host has set the
+    /// channel to blocking mode, and discards what does not fit otherwise.
+    pub fn write(&mut self, bytes: &[u8]) {
+        self.channel.write_all(bytes);
+    }
+}
+
+// SAFETY: The handle is the only way to access its channel, so it can be moved to another thread.
+unsafe impl Send for RttUpChannel {}
+
+impl fmt::Write for RttUpChannel {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// An RTT down channel, to receive commands from the host.
+///
+/// The number of down channels is set at build time with the `DEFMT_PERSIST_RTT_DOWN_CHANNELS`
+/// environment variable (default: 0), and their buffer size with
+/// `DEFMT_PERSIST_RTT_DOWN_BUFFER_SIZE` (default: 64).
+pub struct RttDownChannel {
+    channel: &'static Channel,
+}
+
+impl RttDownChannel {
+    /// Takes down channel `number`, counting from 0.
+    ///
+    /// Returns `None` if the channel does not exist or has already been taken.
+    pub fn take(number: usize) -> Option<Self> {
+        if DOWN_TAKEN.get(number)?.swap(true, Ordering::Relaxed) {
+            return None;
+        }
+        // SAFETY: The channel is only accessed through this handle, which can be taken once.
+        let channel = unsafe { &*_SEGGER_RTT.down_channels[number].get() };
+        Some(RttDownChannel { channel })
+    }
+
+    /// Reads the bytes sent by the host into `buf`, returning how many were read.
+    ///
+    /// Returns 0 if nothing was received. Does not block.
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        self.channel.read(buf)
+    }
+}
+
+// SAFETY: The handle is the only way to access its channel, so it can be moved to another thread.
+unsafe impl Send for RttDownChannel {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn read_down_channel() {
+        // The host wrote 4 bytes, wrapping around the end.
+        let mut buffer = [3, 4, 0, 0, 0, 0, 1, 2].map(MaybeUninit::new);
+        let channel = Channel::new(ptr::null(), buffer.as_mut_ptr(), 8, 0);
+        channel.read.store(6, Ordering::Relaxed);
+        channel.write.store(2, Ordering::Relaxed);
+
+        let mut out = [0; 3];
+        assert_eq!(channel.read(&mut out), 3);
+        assert_eq!(out, [1, 2, 3]);
+        assert_eq!(channel.read(&mut out), 1);
+        assert_eq!(out[0], 4);
+        assert_eq!(channel.read(&mut out), 0);
+
+        // Out of bounds indexes from the host are ignored.
+        channel.write.store(9, Ordering::Relaxed);
+        assert_eq!(channel.read(&mut out), 0);
     }
 }
@@ -1,17 +1,37 @@
-//! A single-producer, single-consumer (SPSC) lock-free queue.
+//! A single-producer, single-consumer (SPSC) queue, see [`RingBuffer`].
 
+use crate::{
+    LogLevel, RecoveryStatus,
+    crc::crc32,
+    frame::{Frames, PeekFrames},
+};
+#[cfg(not(word64))]
+use core::sync::atomic::AtomicU32;
 use core::{
     cell::UnsafeCell,
     mem::MaybeUninit,
     ops::Range,
     ptr, slice,
-    sync::atomic::{AtomicU32, Ordering, compiler_fence, fence},
+    sync::atomic::{Ordering, fence},
 };
 
-/// A single-producer, single-consumer (SPSC) lock-free queue storing up to `len-1` bytes.
+/// A single-producer, single-consumer (SPSC) queue storing up to `len-1` bytes.
 /// `len` is defined by the leftover size of the region after the [`RingBuffer`] has taken its
 /// size.
 ///
+/// # Critical Sections
+///
+/// Data is written and read without locking, but the header checksum covers fields stored by
+/// both sides, so each store to the header and the checksum update after it take a short
+/// critical section:
+///
+/// - [`Producer::commit`] storing `write`, or incrementing the dropped frames counter if the
+///   frame was discarded.
+/// - [`GrantR::release`] storing `read`.
+/// - [`Consumer::set_log_level`].
+/// - With the `overwrite` feature, `reclaim` advancing `read` past the oldest frames, and the
+///   read lock set in [`Consumer::read`] and cleared when a [`GrantR`] is dropped.
+///
 /// # ECC Flush
 ///
 /// On MCUs with 32-bit or 64-bit ECC-protected RAM (e.g., STM32H7/H5), writes are cached
@@ -24,26 +44,100 @@ use core::{
 /// Note: The struct layout changes with this feature, so the MAGIC value differs to
 /// force reinitialization when switching between configurations.
 ///
+/// # Compact Header
+///
+/// With the `compact-header` feature, `header` is a single word holding a 16-bit magic in its
+/// upper half and the metadata packed in its lower half, see `Meta::packed`. This shrinks the
+/// struct from 48 to 16 bytes on 32-bit ARM (24 with `ecc`) and lowers its alignment to 4
+/// bytes, which makes regions of a few hundred bytes, e.g. a backup SRAM, practical. The boot
+/// count wraps at 256 and the dropped frames counter at 32, the reset reason and the firmware
+/// identifier are not kept. A random bit pattern is more likely to match the shorter magic, but
+/// the `checksum` still has to match before any logs are recovered.
+///
+/// # Word Writes
+///
+/// Some retained RAMs, and ECC RAMs with 64-bit words, corrupt a word on a partial write. With
+/// the `word-write` feature, [`Producer::write`] stages the bytes of the current word and only
+/// writes whole, aligned words to the buffer. [`Producer::commit`] pads the last word of a frame
+/// with zero bytes, which decoders skip like empty frames, so `write` is always at a word
+/// boundary and no word is shared with data the consumer can read. The buffer must start at a
+/// word boundary and hold whole words.
+///
+/// With 64-bit words, each field after the magic is a `Field` taking a word of its own, so the
+/// 32-bit header stores do not write words partially either. This grows the struct to 80 bytes
+/// on 32-bit ARM (88 with `ecc`), or 32 bytes with `compact-header` (40 with `ecc`), with its
+/// own magic.
+///
+/// # Checksum
+///
+/// The indexes and the metadata are covered by a CRC-32 in `checksum`, which is updated after
+/// every store. A bit flip in an index is therefore detected on recovery, instead of silently
+/// shifting the recovered window. Stores and checksum updates happen in a critical section, as
+/// both the producer and the consumer store indexes.
+///
+/// A reset between a store to an index and its checksum update, or a bit flip in an index,
+/// leaves a mismatch from which the previous value of that index is computed on recovery. If
+/// the mismatch cannot be explained by a single index, the recovered logs are discarded.
+///
+/// # Overwrite Mode
+///
+/// By default, [`Producer::write`] discards data that does not fit. With the `overwrite`
+/// feature, the producer instead advances `read` past whole zero-delimited frames to make
+/// room, so the buffer always holds the most recent logs. While the consumer holds a
+/// [`GrantR`], the `READ_LOCK` bit is set in `read` and the producer falls back to
+/// discarding, so no memory handed out to the consumer is ever overwritten.
+///
 /// # CPU Data Cache
 ///
 /// On Cortex-M7 and other cores with a data cache, ensure the persist memory region is
 /// configured as non-cacheable via the MPU. Otherwise, data may be lost in the CPU cache
 /// on reset, even with ECC flushing enabled. Cortex-M0/M0+/M3/M4 do not have a data cache.
+///
+/// Alternatively, enable the `dcache` feature to clean the written data cache lines by address
+/// after every [`Producer::write`], and the header after every store to it, e.g. in
+/// [`Producer::commit`] and [`GrantR::release`].
 #[repr(C)]
 pub struct RingBuffer {
     /// If the value is [`MAGIC`], the struct is initialized.
     ///
     /// In particular, this means that the reader-owned part of the buffer
     /// contains real data.
-    header: u128,
+    #[cfg(not(feature = "compact-header"))]
+    header: Magic,
+    /// [`MAGIC`] in the upper 16 bits, the struct is initialized if they match. The lower 16
+    /// bits hold the metadata, which is stored in separate fields without `compact-header`.
+    #[cfg(feature = "compact-header")]
+    header: Field,
     /// Where the next read starts.
     ///
-    /// The RingBuffer always guarantees `read < len`.
-    read: AtomicU32,
+    /// The RingBuffer always guarantees `read < len`, ignoring the `READ_LOCK` bit.
+    read: Field,
     /// Where the next write starts.
     ///
     /// The RingBuffer always guarantees `write < len`.
-    write: AtomicU32,
+    write: Field,
+    /// Number of frames discarded because they did not fit in the buffer.
+    ///
+    /// Only written by the producer. Wraps on overflow.
+    #[cfg(not(feature = "compact-header"))]
+    dropped: Field,
+    /// Number of times the buffer was recovered since it was initialized.
+    ///
+    /// Only written during recovery. Wraps on overflow.
+    #[cfg(not(feature = "compact-header"))]
+    boot_count: Field,
+    /// Reset reason recorded by the latest run, which tells why the run before it ended, see
+    /// `ResetReason::to_bits`.
+    #[cfg(not(feature = "compact-header"))]
+    reset_reason: Field,
+    /// Firmware identifier recorded by the latest run, or 0 if none was recorded.
+    #[cfg(not(feature = "compact-header"))]
+    firmware_id: Field,
+    /// Minimum level of the frames stored by the logger, see `LogLevel::to_bits`.
+    #[cfg(not(feature = "compact-header"))]
+    log_level: Field,
+    /// CRC-32 of the fields above, excluding the magic and the `READ_LOCK` bit.
+    checksum: Field,
     /// Writing a single byte to this field flushes the ECC write cache.
     /// An unaligned write to a different SRAM word forces the cache to commit.
     #[cfg(feature = "ecc")]
@@ -51,9 +145,20 @@ pub struct RingBuffer {
 }
 
 /// Writes data into the buffer.
+///
+/// Data is written one frame at a time: [`Producer::write`] appends to the current frame,
+/// and [`Producer::commit`] makes it visible to the [`Consumer`]. A frame that does not fit
+/// is discarded as a whole, so the buffer never holds a partially written frame.
 pub struct Producer<'a> {
     header: &'a RingBuffer,
     buf: &'a [UnsafeCell<MaybeUninit<u8>>],
+    /// Number of bytes written after `write` that are not yet committed.
+    pending: usize,
+    /// Set when the current frame did not fit. It is dropped on the next commit.
+    discard: bool,
+    /// Bytes of the current word that are not written to `buf` yet.
+    #[cfg(feature = "word-write")]
+    stage: [u8; size_of::<Word>()],
 }
 
 /// Reads data previously written to the buffer.
@@ -74,17 +179,255 @@ pub struct Consumer<'a> {
 // - The UnsafeCell slice is only accessed through methods that maintain the SPSC invariant
 unsafe impl Send for Consumer<'_> {}
 
+/// Type of the `header` field.
+#[cfg(not(feature = "compact-header"))]
+type Magic = u128;
+/// Type of the `header` field with the `compact-header` feature, as read during recovery.
+#[cfg(feature = "compact-header")]
+type Magic = u32;
+
+// Word is generated by build.rs from the DEFMT_PERSIST_WORD_SIZE env var.
+#[cfg(feature = "word-write")]
+include!(concat!(env!("OUT_DIR"), "/word.rs"));
+
+/// Type of the header fields after the magic.
+#[cfg(not(word64))]
+type Field = AtomicU32;
+
+/// Header field holding a `u32` in a 64-bit word of its own, with 64-bit words.
+///
+/// A 32-bit store would be a partial write to the word, so [`Field::store`] writes the whole
+/// word, with the value in its first 4 bytes and zeros in the rest. Targets with 64-bit words in
+/// their RAM do not have 64-bit atomics, so the accesses are volatile instead, with fences for
+/// the ordering. Like the `ecc` flush, this relies on aligned accesses not being torn. All
+/// stores happen in critical sections or during recovery, like with atomic fields.
+#[cfg(word64)]
+#[repr(C, align(8))]
+struct Field(UnsafeCell<u64>);
+
+#[cfg(word64)]
+impl Field {
+    #[cfg(any(test, feature = "flash"))]
+    const fn new(value: u32) -> Self {
+        Field(UnsafeCell::new(Self::word(value)))
+    }
+
+    /// Returns the word holding `value`.
+    const fn word(value: u32) -> u64 {
+        let [a, b, c, d] = value.to_ne_bytes();
+        u64::from_ne_bytes([a, b, c, d, 0, 0, 0, 0])
+    }
+
+    #[inline]
+    fn load(&self, order: Ordering) -> u32 {
+        // SAFETY: The value is in the first 4 bytes of the word, which is aligned and valid for
+        // any bit pattern.
+        let value = unsafe { self.0.get().cast::<u32>().read_volatile() };
+        if order != Ordering::Relaxed {
+            fence(order);
+        }
+        value
+    }
+
+    #[inline]
+    fn store(&self, value: u32, order: Ordering) {
+        if order != Ordering::Relaxed {
+            fence(order);
+        }
+        // SAFETY: The word is aligned and owned by this field. See above for concurrent access.
+        unsafe { self.0.get().write_volatile(Self::word(value)) };
+    }
+}
+
 /// Value used to indicate that the queue is initialized.
 ///
-/// Replace this if the layout or field semantics change in a backwards-incompatible way.
-/// The `ecc` layout uses a different magic to force reinitialization when switching.
-#[cfg(not(feature = "ecc"))]
-const MAGIC: u128 = 0xb528_c25f_90c6_16af_cbc1_502c_09c1_fd6e;
-#[cfg(feature = "ecc")]
-const MAGIC: u128 = 0x1dff_2060_27b9_f2b4_a194_1013_69cd_3c6c;
+/// Replace these if the layout or field semantics change in a backwards-incompatible way.
+/// The `ecc` layout and the layout for 64-bit words use different magics to force
+/// reinitialization when switching.
+#[cfg(any(not(feature = "compact-header"), feature = "host"))]
+pub(crate) const MAGIC_DEFAULT: u128 = 0x8796_3a5a_8304_573c_7cce_dd5c_0e45_b503;
+/// Value of [`MAGIC`] with the `ecc` feature.
+#[cfg(any(not(feature = "compact-header"), feature = "host"))]
+pub(crate) const MAGIC_ECC: u128 = 0xdd74_4dc9_ee43_46de_1381_8ba2_4d2e_e183;
+/// Value of [`MAGIC`] with 64-bit words.
+#[cfg(any(not(feature = "compact-header"), feature = "host"))]
+pub(crate) const MAGIC_WORD64: u128 = 0x1cd8_86cf_5084_ed62_a73a_60ec_487c_d13e;
+/// Value of [`MAGIC`] with the `ecc` feature and 64-bit words.
+#[cfg(any(not(feature = "compact-header"), feature = "host"))]
+pub(crate) const MAGIC_WORD64_ECC: u128 = 0x7178_a915_ce9c_88a1_0e16_b9b2_933d_18ee;
+/// Value of [`MAGIC`] with the `compact-header` feature, in the upper 16 bits.
+#[cfg(any(feature = "compact-header", feature = "host"))]
+pub(crate) const MAGIC_COMPACT: u32 = 0x5eb3_0000;
+/// Value of [`MAGIC`] with the `compact-header` and `ecc` features, in the upper 16 bits.
+#[cfg(any(feature = "compact-header", feature = "host"))]
+pub(crate) const MAGIC_COMPACT_ECC: u32 = 0x9c4d_0000;
+/// Value of [`MAGIC`] with the `compact-header` feature and 64-bit words, in the upper 16 bits.
+#[cfg(any(feature = "compact-header", feature = "host"))]
+pub(crate) const MAGIC_COMPACT_WORD64: u32 = 0x8569_0000;
+/// Value of [`MAGIC`] with the `compact-header` and `ecc` features and 64-bit words, in the
+/// upper 16 bits.
+#[cfg(any(feature = "compact-header", feature = "host"))]
+pub(crate) const MAGIC_COMPACT_WORD64_ECC: u32 = 0x18b7_0000;
+#[cfg(not(feature = "compact-header"))]
+const MAGIC: Magic = match (cfg!(feature = "ecc"), cfg!(word64)) {
+    (false, false) => MAGIC_DEFAULT,
+    (true, false) => MAGIC_ECC,
+    (false, true) => MAGIC_WORD64,
+    (true, true) => MAGIC_WORD64_ECC,
+};
+#[cfg(feature = "compact-header")]
+const MAGIC: Magic = match (cfg!(feature = "ecc"), cfg!(word64)) {
+    (false, false) => MAGIC_COMPACT,
+    (true, false) => MAGIC_COMPACT_ECC,
+    (false, true) => MAGIC_COMPACT_WORD64,
+    (true, true) => MAGIC_COMPACT_WORD64_ECC,
+};
+
+/// [`MAGIC`] as the 64-bit words of the `header` field, which are written whole.
+#[cfg(all(word64, not(feature = "compact-header")))]
+const MAGIC_WORDS: [u64; 2] = if cfg!(target_endian = "little") {
+    [MAGIC as u64, (MAGIC >> 64) as u64]
+} else {
+    [(MAGIC >> 64) as u64, MAGIC as u64]
+};
+/// [`MAGIC`] as the 64-bit word of the compact `header` field, which is written whole.
+#[cfg(all(word64, feature = "compact-header"))]
+const MAGIC_WORDS: [u64; 1] = [Field::word(MAGIC)];
+
+/// Mask of the metadata in a compact `header`, the magic is in the other bits.
+#[cfg(any(feature = "compact-header", feature = "host"))]
+pub(crate) const META: u32 = 0xffff;
+
+/// Returns whether a recovered `header` marks the struct as initialized.
+#[cfg(not(feature = "compact-header"))]
+const fn has_magic(header: Magic) -> bool {
+    header == MAGIC
+}
+
+/// Returns whether a recovered `header` marks the struct as initialized.
+#[cfg(feature = "compact-header")]
+const fn has_magic(header: Magic) -> bool {
+    header & !META == MAGIC
+}
+
+/// Metadata stored in the header besides the indexes.
+#[derive(Clone, Copy)]
+pub(crate) enum Meta {
+    /// Number of frames discarded because they did not fit in the buffer.
+    Dropped,
+    /// Number of times the buffer was recovered since it was initialized.
+    BootCount,
+    /// Reset reason recorded by the latest run, which tells why the run before it ended.
+    ResetReason,
+    /// Firmware identifier recorded by the latest run.
+    FirmwareId,
+    /// Minimum level of the frames stored by the logger.
+    LogLevel,
+}
+
+impl Meta {
+    /// Returns the position and width of this field in the lower 16 bits of a compact
+    /// `header`, or `None` if it is not kept there.
+    #[cfg(any(feature = "compact-header", feature = "host"))]
+    const fn packed(self) -> Option<(u32, u32)> {
+        match self {
+            Meta::BootCount => Some((0, 8)),
+            Meta::LogLevel => Some((8, 3)),
+            Meta::Dropped => Some((11, 5)),
+            Meta::ResetReason | Meta::FirmwareId => None,
+        }
+    }
+
+    /// Extracts this field from a compact `header`, or returns 0 if it is not kept there.
+    #[cfg(any(feature = "compact-header", feature = "host"))]
+    pub(crate) const fn unpack(self, header: u32) -> u32 {
+        match self.packed() {
+            Some((shift, bits)) => (header >> shift) & ((1 << bits) - 1),
+            None => 0,
+        }
+    }
+
+    /// Replaces this field in a compact `header` with `value`, which wraps at the width of the
+    /// field.
+    #[cfg(feature = "compact-header")]
+    const fn pack(self, header: u32, value: u32) -> u32 {
+        match self.packed() {
+            Some((shift, bits)) => {
+                let mask = ((1 << bits) - 1) << shift;
+                header & !mask | (value << shift) & mask
+            }
+            None => header,
+        }
+    }
+}
+
+/// Mask of the dropped frames counter, which wraps at its width.
+#[cfg(not(feature = "compact-header"))]
+pub(crate) const DROPPED_MASK: u32 = u32::MAX;
+/// Mask of the dropped frames counter with the `compact-header` feature.
+#[cfg(feature = "compact-header")]
+pub(crate) const DROPPED_MASK: u32 = Meta::Dropped.unpack(u32::MAX);
+
+/// Size of [`RingBuffer`] on 32-bit ARM, where `u128` is 8-byte aligned.
+///
+/// Used to parse dumps on the host, where the alignment may differ.
+#[cfg(any(target_arch = "arm", feature = "host"))]
+pub(crate) const ARM_SIZE_DEFAULT: usize = 48;
+/// Size of [`RingBuffer`] on 32-bit ARM with the `ecc` feature.
+#[cfg(any(target_arch = "arm", feature = "host"))]
+pub(crate) const ARM_SIZE_ECC: usize = 56;
+/// Size of [`RingBuffer`] on 32-bit ARM with the `compact-header` feature.
+#[cfg(any(target_arch = "arm", feature = "host"))]
+pub(crate) const ARM_SIZE_COMPACT: usize = 16;
+/// Size of [`RingBuffer`] on 32-bit ARM with the `compact-header` and `ecc` features, where
+/// `_ecc_flush` is 8-byte aligned.
+#[cfg(any(target_arch = "arm", feature = "host"))]
+pub(crate) const ARM_SIZE_COMPACT_ECC: usize = 24;
+/// Size of [`RingBuffer`] on 32-bit ARM with 64-bit words, where each field after the magic
+/// takes a word.
+#[cfg(any(target_arch = "arm", feature = "host"))]
+pub(crate) const ARM_SIZE_WORD64: usize = 80;
+/// Size of [`RingBuffer`] on 32-bit ARM with the `ecc` feature and 64-bit words.
+#[cfg(any(target_arch = "arm", feature = "host"))]
+pub(crate) const ARM_SIZE_WORD64_ECC: usize = 88;
+/// Size of [`RingBuffer`] on 32-bit ARM with the `compact-header` feature and 64-bit words.
+#[cfg(any(target_arch = "arm", feature = "host"))]
+pub(crate) const ARM_SIZE_COMPACT_WORD64: usize = 32;
+/// Size of [`RingBuffer`] on 32-bit ARM with the `compact-header` and `ecc` features and 64-bit
+/// words.
+#[cfg(any(target_arch = "arm", feature = "host"))]
+pub(crate) const ARM_SIZE_COMPACT_WORD64_ECC: usize = 40;
+
+#[cfg(target_arch = "arm")]
+const _: () = assert!(
+    size_of::<RingBuffer>()
+        == match (
+            cfg!(feature = "compact-header"),
+            cfg!(feature = "ecc"),
+            cfg!(word64),
+        ) {
+            (false, false, false) => ARM_SIZE_DEFAULT,
+            (false, true, false) => ARM_SIZE_ECC,
+            (true, false, false) => ARM_SIZE_COMPACT,
+            (true, true, false) => ARM_SIZE_COMPACT_ECC,
+            (false, false, true) => ARM_SIZE_WORD64,
+            (false, true, true) => ARM_SIZE_WORD64_ECC,
+            (true, false, true) => ARM_SIZE_COMPACT_WORD64,
+            (true, true, true) => ARM_SIZE_COMPACT_WORD64_ECC,
+        }
+);
+
+/// Set in `read` while a [`GrantR`] is active, preventing the producer from reclaiming
+/// memory that was handed out to the consumer.
+///
+/// Buffer sizes are bounded by `i32::MAX / 4`, so this bit is never part of a valid index.
+#[cfg(any(feature = "overwrite", feature = "host"))]
+pub(crate) const READ_LOCK: u32 = 1 << 31;
 
-/// Field offsets for corruption testing.
-#[cfg(feature = "qemu-test")]
+/// Field offsets for corruption testing and dump parsing.
+///
+/// The metadata fields only exist without the `compact-header` feature.
+#[cfg(any(feature = "qemu-test", feature = "host"))]
 pub mod offsets {
     use super::RingBuffer;
     use core::mem::{offset_of, size_of};
@@ -96,19 +439,157 @@ pub mod offsets {
     pub const READ: usize = offset_of!(RingBuffer, read);
     /// Offset of the write index field.
     pub const WRITE: usize = offset_of!(RingBuffer, write);
-    /// Size of an index field.
+    /// Offset of the dropped frames counter.
+    #[cfg(not(feature = "compact-header"))]
+    pub const DROPPED: usize = offset_of!(RingBuffer, dropped);
+    /// Offset of the boot counter.
+    #[cfg(not(feature = "compact-header"))]
+    pub const BOOT_COUNT: usize = offset_of!(RingBuffer, boot_count);
+    /// Offset of the reset reason.
+    #[cfg(not(feature = "compact-header"))]
+    pub const RESET_REASON: usize = offset_of!(RingBuffer, reset_reason);
+    /// Offset of the firmware identifier.
+    #[cfg(not(feature = "compact-header"))]
+    pub const FIRMWARE_ID: usize = offset_of!(RingBuffer, firmware_id);
+    /// Offset of the runtime log level.
+    #[cfg(not(feature = "compact-header"))]
+    pub const LOG_LEVEL: usize = offset_of!(RingBuffer, log_level);
+    /// Offset of the header checksum.
+    pub const CHECKSUM: usize = offset_of!(RingBuffer, checksum);
+    /// Size of the value of an index field, at the start of the field.
+    ///
+    /// With the `word-write` feature and 64-bit words, each field takes a word of its own.
     pub const INDEX_SIZE: usize = size_of::<AtomicU32>();
 }
 
 impl RingBuffer {
-    #[cfg(test)]
+    /// Creates an empty buffer header, for a buffer that is not recovered after a reset.
+    #[cfg(any(test, feature = "flash"))]
     pub(crate) fn new(read: u32, write: u32) -> Self {
-        RingBuffer {
+        let rb = RingBuffer {
+            #[cfg(not(feature = "compact-header"))]
             header: MAGIC,
-            read: AtomicU32::new(read),
-            write: AtomicU32::new(write),
+            #[cfg(feature = "compact-header")]
+            header: Field::new(MAGIC),
+            read: Field::new(read),
+            write: Field::new(write),
+            #[cfg(not(feature = "compact-header"))]
+            dropped: Field::new(0),
+            #[cfg(not(feature = "compact-header"))]
+            boot_count: Field::new(0),
+            #[cfg(not(feature = "compact-header"))]
+            reset_reason: Field::new(0),
+            #[cfg(not(feature = "compact-header"))]
+            firmware_id: Field::new(0),
+            #[cfg(not(feature = "compact-header"))]
+            log_level: Field::new(0),
+            checksum: Field::new(0),
             #[cfg(feature = "ecc")]
             _ecc_flush: UnsafeCell::new(0),
+        };
+        rb.update_checksum();
+        rb
+    }
+
+    /// Computes the CRC-32 over the fields covered by `checksum`.
+    fn compute_checksum(&self) -> u32 {
+        checksum(&self.checksum_fields())
+    }
+
+    /// Returns the fields covered by `checksum`, in layout order.
+    #[cfg(not(feature = "compact-header"))]
+    fn checksum_fields(&self) -> [u32; 7] {
+        let read = self.read.load(Ordering::Relaxed);
+        #[cfg(feature = "overwrite")]
+        let read = read & !READ_LOCK;
+        [
+            read,
+            self.write.load(Ordering::Relaxed),
+            self.dropped.load(Ordering::Relaxed),
+            self.boot_count.load(Ordering::Relaxed),
+            self.reset_reason.load(Ordering::Relaxed),
+            self.firmware_id.load(Ordering::Relaxed),
+            self.log_level.load(Ordering::Relaxed),
+        ]
+    }
+
+    /// Returns the fields covered by `checksum`, the indexes and then the packed metadata.
+    #[cfg(feature = "compact-header")]
+    fn checksum_fields(&self) -> [u32; 3] {
+        let read = self.read.load(Ordering::Relaxed);
+        #[cfg(feature = "overwrite")]
+        let read = read & !READ_LOCK;
+        [
+            read,
+            self.write.load(Ordering::Relaxed),
+            self.header.load(Ordering::Relaxed) & META,
+        ]
+    }
+
+    /// Loads a metadata field.
+    #[inline]
+    fn load_meta(&self, meta: Meta, order: Ordering) -> u32 {
+        #[cfg(not(feature = "compact-header"))]
+        {
+            self.meta_field(meta).load(order)
+        }
+        #[cfg(feature = "compact-header")]
+        {
+            meta.unpack(self.header.load(order))
+        }
+    }
+
+    /// Stores a metadata field.
+    ///
+    /// With the `compact-header` feature, the value wraps at the width of the field, and the
+    /// reset reason and firmware identifier are not stored. The metadata shares a word there, so
+    /// this must only be called in a critical section or during recovery, like every store to
+    /// the header.
+    #[inline]
+    fn store_meta(&self, meta: Meta, value: u32, order: Ordering) {
+        #[cfg(not(feature = "compact-header"))]
+        self.meta_field(meta).store(value, order);
+        #[cfg(feature = "compact-header")]
+        self.header
+            .store(meta.pack(self.header.load(Ordering::Relaxed), value), order);
+    }
+
+    /// Returns the field holding `meta`.
+    #[cfg(not(feature = "compact-header"))]
+    #[inline]
+    fn meta_field(&self, meta: Meta) -> &Field {
+        match meta {
+            Meta::Dropped => &self.dropped,
+            Meta::BootCount => &self.boot_count,
+            Meta::ResetReason => &self.reset_reason,
+            Meta::FirmwareId => &self.firmware_id,
+            Meta::LogLevel => &self.log_level,
+        }
+    }
+
+    /// Updates `checksum` after a store to a covered field.
+    ///
+    /// Must be called in the same critical section as the store, so stores from the producer
+    /// and consumer cannot interleave with the update. Not needed during recovery, where
+    /// there is only one owner.
+    #[inline]
+    fn update_checksum(&self) {
+        // Release: keeps the checksum store after the field stores it covers.
+        self.checksum
+            .store(self.compute_checksum(), Ordering::Release);
+        self.flush_ecc();
+        self.clean_dcache();
+    }
+
+    /// Cleans the data cache lines of the header, so the stores to it reach RAM.
+    ///
+    /// No-op when `dcache` feature is disabled.
+    #[inline]
+    fn clean_dcache(&self) {
+        #[cfg(feature = "dcache")]
+        {
+            let start = ptr::from_ref(self).addr();
+            crate::dcache::clean(start..start + size_of::<Self>());
         }
     }
 
@@ -120,16 +601,25 @@ impl RingBuffer {
         #[cfg(feature = "ecc")]
         {
             // Ensure previous writes are emitted before the volatile write.
-            compiler_fence(Ordering::SeqCst);
+            core::sync::atomic::compiler_fence(Ordering::SeqCst);
             // SAFETY: Writing a single byte to our own `UnsafeCell` field is safe.
             // This unaligned access to a different SRAM word flushes the ECC cache.
             // Concurrent writes from Producer and Consumer are safe because:
             // - Single-byte writes are atomic on all supported platforms.
             // - The value written is always 0; we don't care about the result.
+            #[cfg(not(feature = "word-write"))]
             unsafe {
                 let ptr: *mut u8 = self._ecc_flush.get().cast();
                 ptr.write_volatile(0);
             }
+            // SAFETY: As above, but a whole word is written for RAMs that corrupt on partial
+            // writes. `_ecc_flush` is a `u64`, so it is large and aligned enough for a `Word`.
+            // A torn write still leaves it 0.
+            #[cfg(feature = "word-write")]
+            unsafe {
+                let ptr: *mut Word = self._ecc_flush.get().cast();
+                ptr.write_volatile(0);
+            }
         }
     }
     /// Creates a `RingBuffer` or recovers previous state if available.
@@ -151,19 +641,21 @@ impl RingBuffer {
     /// afterwards.
     ///
     /// There is always a risk that corrupt memory is accepted as
-    /// valid. While this function checks for direct memory safety problems,
-    /// it cannot vet the data in a non-empty buffer. Treat it as external
-    /// input and do not rely on its value for memory safety.
+    /// valid. While this function checks for direct memory safety problems
+    /// and validates the header checksum, it cannot vet the data in a
+    /// non-empty buffer. Treat it as external input and do not rely on its
+    /// value for memory safety.
     pub(crate) unsafe fn recover_or_reinitialize(
         memory: Range<usize>,
-    ) -> (Producer<'static>, Consumer<'static>) {
+    ) -> (Producer<'static>, Consumer<'static>, RecoveryStatus) {
         let v: *mut Self = ptr::with_exposed_provenance_mut(memory.start);
         let buf_len = memory.len() - size_of::<RingBuffer>();
 
         // SAFETY:
         // - Alignment is guaranteed by the caller.
         // - Size is guaranteed by the caller.
-        // - All fields (`u128`, `AtomicU32`, `UnsafeCell<u64>`, `[UnsafeCell<MaybeUninit<u8>>, X]`)
+        // - All fields (`u128` or `u32`, `AtomicU32` or `Field`, `UnsafeCell<u64>`,
+        //   `[UnsafeCell<MaybeUninit<u8>>, X]`)
         //   are valid for any bit pattern, so interpreting the raw memory as this
         //   type and buffer is sound. As the memory is initialized outside the Rust abstract
         //   machine (of the running program), we consider the caveats of non-fixed
@@ -172,45 +664,75 @@ impl RingBuffer {
         //   program execution for any given `memory`, ensuring no aliasing
         //   references exist for the `'static` lifetime.
         let v = unsafe { &mut *v };
-        let header = ptr::from_mut(&mut v.header);
+        let header: *mut Magic = ptr::from_mut(&mut v.header).cast();
         // SAFETY: A regular read from v.header would be safe here, but it would maybe be
         // optimizsed away.
-        if unsafe { header.read_volatile() } != MAGIC {
+        let status = if !has_magic(unsafe { header.read_volatile() }) {
             v.read.store(0, Ordering::Relaxed);
             // The intermediate state doesn't matter until header == MAGIC
             v.write.store(0, Ordering::Relaxed);
-            v.flush_ecc();
+            // With `compact-header`, this keeps the invalid magic, and clears the metadata
+            // before the checksum covers it.
+            for meta in [
+                Meta::Dropped,
+                Meta::BootCount,
+                Meta::ResetReason,
+                Meta::FirmwareId,
+                Meta::LogLevel,
+            ] {
+                v.store_meta(meta, 0, Ordering::Relaxed);
+            }
+            v.update_checksum();
 
             fence(Ordering::SeqCst);
             // SAFETY: A regular assignment to v.header would be safe
             // here, but is not guaranteed to actually update memory. This
             // must mean the pointer is valid for writes and properly
             // aligned.
-            unsafe { header.write_volatile(MAGIC) };
+            #[cfg(not(word64))]
+            unsafe {
+                header.write_volatile(MAGIC)
+            };
+            #[cfg(word64)]
+            for (i, word) in MAGIC_WORDS.into_iter().enumerate() {
+                // SAFETY: As above. `header` is a whole number of aligned 64-bit words.
+                unsafe { header.cast::<u64>().add(i).write_volatile(word) };
+            }
+            v.clean_dcache();
+            RecoveryStatus::Reinitialized
         } else {
-            // The header promised to keep the contract, but we don't
-            // trust it for the safety of our pointer offsets.
-            let write = v.write.load(Ordering::Relaxed) as usize;
-            let read = v.read.load(Ordering::Relaxed) as usize;
-            let read_ok = read < buf_len;
-            let write_ok = write < buf_len;
+            // A reset while a `GrantR` was active leaves the lock bit set. Clearing it
+            // cannot turn an invalid index into a valid one, and is not covered by the checksum.
+            #[cfg(feature = "overwrite")]
+            v.read.store(
+                v.read.load(Ordering::Relaxed) & !READ_LOCK,
+                Ordering::Relaxed,
+            );
+
+            let (read, write, status) = repair_indices(
+                &v.checksum_fields(),
+                v.checksum.load(Ordering::Relaxed),
+                buf_len,
+            );
             // Since `header` is already marked as valid, some extra care
             // is taken here to avoid situations where there is a gap of time
             // where both indexes are in-bounds, but not valid. Otherwise
             // a poorly timed reset could leave the queue in a state that
-            // appears valid and non-empty.
-            match (read_ok, write_ok) {
-                (true, true) => {}
-                (true, false) => v.write.store(read as u32, Ordering::Relaxed),
-                (false, true) => v.read.store(write as u32, Ordering::Relaxed),
-                (false, false) => {
-                    v.read.store(0, Ordering::Relaxed);
-                    // write is still invalid between these operations
-                    v.write.store(0, Ordering::Relaxed);
-                }
-            };
-            v.flush_ecc();
-        }
+            // appears valid and non-empty. `repair_indices` only changes one
+            // index to an in-bounds value, or resets both to 0, so storing
+            // `read` first never exposes such a state. The checksum is only
+            // updated once the indexes are repaired, which also covers this.
+            v.read.store(read, Ordering::Relaxed);
+            v.write.store(write, Ordering::Relaxed);
+            let boot_count = v.load_meta(Meta::BootCount, Ordering::Relaxed);
+            v.store_meta(
+                Meta::BootCount,
+                boot_count.wrapping_add(1),
+                Ordering::Relaxed,
+            );
+            v.update_checksum();
+            status
+        };
         fence(Ordering::SeqCst);
 
         // SAFETY:
@@ -225,8 +747,53 @@ impl RingBuffer {
             )
         };
 
+        #[cfg(feature = "word-write")]
+        v.align_write(buf);
+
         // SAFETY: The caller guarantees buf.len() < i32::MAX / 4.
-        unsafe { v.split(buf) }
+        let (p, c) = unsafe { v.split(buf) };
+        (p, c, status)
+    }
+
+    /// Moves a recovered `write` that is not at a word boundary to one, e.g. after a run
+    /// without the `word-write` feature.
+    ///
+    /// The rest of the word is padded with zero bytes. If that would overwrite unread data,
+    /// `write` is moved back to the start of the word instead, cutting the newest frame, which
+    /// decoders skip up to the next delimiter.
+    ///
+    /// Must only be called during recovery, while there is only one owner. `buf` must start at a
+    /// word boundary and hold whole words.
+    #[cfg(feature = "word-write")]
+    fn align_write(&self, buf: &[UnsafeCell<MaybeUninit<u8>>]) {
+        let read = self.read.load(Ordering::Relaxed) as usize;
+        let write = self.write.load(Ordering::Relaxed) as usize;
+        let offset = write % size_of::<Word>();
+        if offset == 0 {
+            return;
+        }
+        let start = write - offset;
+        let end = start + size_of::<Word>();
+        let new_write = if (write + 1..end).contains(&read) {
+            start
+        } else {
+            let word: *mut MaybeUninit<Word> = buf[start..].as_ptr().cast_mut().cast();
+            // SAFETY: `buf` starts at a word boundary and holds whole words, so the word at
+            // `start` is aligned and inside `buf`. There is no other owner during recovery.
+            // The word is read and written as `MaybeUninit`, as the free bytes may be
+            // uninitialized.
+            unsafe {
+                let mut value = word.read_volatile();
+                let bytes: *mut u8 = value.as_mut_ptr().cast();
+                bytes.add(offset).write_bytes(0, size_of::<Word>() - offset);
+                word.write_volatile(value);
+            }
+            #[cfg(feature = "dcache")]
+            crate::dcache::clean(word.addr()..word.addr() + size_of::<Word>());
+            end % buf.len()
+        };
+        self.write.store(new_write as u32, Ordering::Relaxed);
+        self.update_checksum();
     }
 
     /// Splits the queue into producer and consumer given a memory area.
@@ -240,12 +807,108 @@ impl RingBuffer {
         buf: &'a [UnsafeCell<MaybeUninit<u8>>],
     ) -> (Producer<'a>, Consumer<'a>) {
         (
-            Producer { header: self, buf },
+            Producer {
+                header: self,
+                buf,
+                pending: 0,
+                discard: false,
+                #[cfg(feature = "word-write")]
+                stage: [0; size_of::<Word>()],
+            },
             Consumer { header: self, buf },
         )
     }
 }
 
+/// Computes the CRC-32 stored in `checksum` from the covered fields, in layout order.
+pub(crate) fn checksum<const N: usize>(fields: &[u32; N]) -> u32 {
+    let mut bytes = [[0; 4]; N];
+    for (chunk, field) in bytes.iter_mut().zip(fields) {
+        *chunk = field.to_le_bytes();
+    }
+    crc32(bytes.as_flattened())
+}
+
+/// Validates recovered indexes for a buffer of `buf_len` bytes.
+///
+/// The header promised to keep the contract, but we don't trust it for the safety of our
+/// pointer offsets. `fields` are the fields covered by `checksum`, as recovered, and
+/// `stored` is the recovered checksum. Returns the `(read, write)` indexes to use and how they
+/// were recovered.
+///
+/// A mismatch usually means a reset between the store to an index and the checksum update, or
+/// a bit flip in an index. The index the checksum was computed with is then found from the
+/// mismatch, and the indexes recovering less data are kept. If that fails, the buffer is left
+/// empty.
+pub(crate) fn repair_indices<const N: usize>(
+    fields: &[u32; N],
+    stored: u32,
+    buf_len: usize,
+) -> (u32, u32, RecoveryStatus) {
+    let (read, write) = (fields[0], fields[1]);
+    let in_bounds =
+        |&(read, write): &(u32, u32)| (read as usize) < buf_len && (write as usize) < buf_len;
+    let mismatch = checksum(fields) ^ stored;
+    if mismatch == 0 && in_bounds(&(read, write)) {
+        return (read, write, RecoveryStatus::Valid);
+    }
+
+    // The index the checksum was computed with, assuming either one changed. Assuming the
+    // wrong one almost certainly gives an index out of bounds.
+    let old = [
+        (read ^ field_delta::<N>(0, mismatch), write),
+        (read, write ^ field_delta::<N>(1, mismatch)),
+    ]
+    .into_iter()
+    .find(|old| mismatch != 0 && in_bounds(old));
+    if let Some(old) = old {
+        // Keeps data already released by the consumer, or a frame not committed in time,
+        // from being recovered.
+        let len = |&(read, write): &(u32, u32)| (write + buf_len as u32 - read) % buf_len as u32;
+        let (read, write) = Some((read, write))
+            .filter(in_bounds)
+            .map_or(old, |new| core::cmp::min_by_key(old, new, len));
+        return (read, write, RecoveryStatus::IndicesRepaired);
+    }
+
+    let (read, write) = match ((read as usize) < buf_len, (write as usize) < buf_len) {
+        (_, true) => (write, write),
+        (true, false) => (read, read),
+        (false, false) => (0, 0),
+    };
+    (read, write, RecoveryStatus::Discarded)
+}
+
+/// Returns the change to field `index` that changes the checksum by `mismatch`.
+///
+/// The CRC-32 of a fixed-length input is affine, so the checksum changes by a linear function
+/// of the change to a field, which is solved for here. This function is invertible, as a
+/// CRC-32 detects every change within 32 bits.
+fn field_delta<const N: usize>(index: usize, mismatch: u32) -> u32 {
+    let zero = checksum(&[0; N]);
+    // Reduced rows of the linear function, as (checksum change, field change), by highest bit.
+    let mut rows = [(0u32, 0u32); 32];
+    for bit in 0..32 {
+        let mut fields = [0; N];
+        fields[index] = 1 << bit;
+        let mut row = (checksum(&fields) ^ zero, 1 << bit);
+        while row.0 != 0 {
+            let high = 31 - row.0.leading_zeros() as usize;
+            if rows[high].0 == 0 {
+                rows[high] = row;
+                break;
+            }
+            row = (row.0 ^ rows[high].0, row.1 ^ rows[high].1);
+        }
+    }
+    let mut row = (mismatch, 0);
+    while row.0 != 0 {
+        let high = 31 - row.0.leading_zeros() as usize;
+        row = (row.0 ^ rows[high].0, row.1 ^ rows[high].1);
+    }
+    row.1
+}
+
 impl Producer<'_> {
     /// How much space is left in the buffer?
     #[inline]
@@ -257,21 +920,137 @@ impl Producer<'_> {
         }
     }
 
-    /// Appends `data` to the buffer.
+    /// Advances `read` past whole frames until `needed` bytes are available after `write`.
+    ///
+    /// Frames are delimited by zero bytes, as in the rzCOBS encoding. If no frame boundary
+    /// frees enough space, all committed data up to `committed` is dropped. Nothing is
+    /// reclaimed while the consumer holds a [`GrantR`], or after it leaked one, until its next
+    /// [`Consumer::read`].
+    ///
+    /// Returns the read index to use for the write.
+    #[cfg(feature = "overwrite")]
+    fn reclaim(&self, read: u32, committed: usize, write: usize, needed: usize) -> usize {
+        if read & READ_LOCK != 0 {
+            return (read & !READ_LOCK) as usize;
+        }
+
+        let buf: *const u8 = self.buf.as_ptr().cast();
+        let mut cursor = read as usize;
+        let mut new_read = committed;
+        while cursor != committed {
+            // SAFETY: `cursor` stays within `read..committed` (modulo wrapping), which is the
+            // consumer-owned part of `buf` and therefore initialized. The consumer only reads
+            // this memory, and only while holding a `GrantR`, so there is no concurrent write.
+            let byte = unsafe { buf.add(cursor).read() };
+            cursor = (cursor + 1) % self.buf.len();
+            if byte == 0 && self.available(cursor, write) >= needed {
+                new_read = cursor;
+                break;
+            }
+        }
+
+        critical_section::with(|_| {
+            // The consumer only stores `read` in critical sections, so this check and the store
+            // below cannot interleave with it, without an atomic read-modify-write that
+            // ARMv6-M lacks. Acquire: synchronizes with the consumer's Release in
+            // `GrantR::release`, so it is done reading the memory we are about to overwrite.
+            // If the consumer has taken a grant or released data in the meantime, use its
+            // value instead.
+            let current = self.header.read.load(Ordering::Acquire);
+            if current != read {
+                return (current & !READ_LOCK) as usize;
+            }
+            self.header.read.store(new_read as u32, Ordering::Relaxed);
+            self.header.update_checksum();
+            new_read
+        })
+    }
+
+    /// Appends `data` to the current frame.
     ///
-    /// If there is not enough space, the last bytes are silently discarded.
+    /// The data is not visible to the consumer until [`Producer::commit`] is called. If there
+    /// is not enough space, the whole frame is discarded on commit. With the `overwrite`
+    /// feature, the oldest frames are discarded first to make room.
     #[inline]
     pub fn write(&mut self, data: &[u8]) {
+        if self.discard || data.is_empty() {
+            return;
+        }
+        // Space taken by `data`, including the padding of its last word.
+        #[cfg(not(feature = "word-write"))]
+        let needed = data.len();
+        #[cfg(feature = "word-write")]
+        let needed = (self.pending + data.len()).next_multiple_of(size_of::<Word>()) - self.pending;
+        if self.pending + needed >= self.buf.len() {
+            // The frame can never fit, don't discard old frames for it.
+            self.discard = true;
+            return;
+        }
+
+        // Relaxed: producer owns `write`, no cross-thread synchronization needed.
+        let committed = self.header.write.load(Ordering::Relaxed) as usize;
+        // `pending < buf.len()` by the check above, so this is a valid index.
+        let write = (committed + self.pending) % self.buf.len();
+
         // Relaxed: stale `read` is safe (underestimates available space).
+        #[cfg(not(feature = "overwrite"))]
         let read = self.header.read.load(Ordering::Relaxed) as usize;
-        // Relaxed: producer owns `write`, no cross-thread synchronization needed.
-        let write = self.header.write.load(Ordering::Relaxed) as usize;
-        let buf: *mut u8 = self.buf.as_ptr().cast_mut().cast();
-        let len = data.len().min(self.available(read, write));
-        if len == 0 {
+
+        // Acquire: the consumer must be done with memory before we reclaim it.
+        #[cfg(feature = "overwrite")]
+        let read = {
+            let read = self.header.read.load(Ordering::Acquire);
+            let unlocked = (read & !READ_LOCK) as usize;
+            if needed > self.available(unlocked, write) {
+                self.reclaim(read, committed, write, needed)
+            } else {
+                unlocked
+            }
+        };
+
+        if needed > self.available(read, write) {
+            self.discard = true;
             return;
         }
 
+        #[cfg(feature = "word-write")]
+        {
+            let mut pos = write;
+            for &byte in data {
+                let offset = pos % size_of::<Word>();
+                self.stage[offset] = byte;
+                if offset == size_of::<Word>() - 1 {
+                    self.store_stage(pos - offset);
+                }
+                pos = (pos + 1) % self.buf.len();
+            }
+            self.pending += data.len();
+        }
+        #[cfg(not(feature = "word-write"))]
+        self.copy(data, write);
+        #[cfg(feature = "dcache")]
+        self.clean_dcache(write, needed);
+    }
+
+    /// Cleans the data cache lines of the `len` bytes at `start` in `buf`, wrapping around its
+    /// end, so the stores to them reach RAM.
+    #[cfg(feature = "dcache")]
+    #[inline]
+    fn clean_dcache(&self, start: usize, len: usize) {
+        let buf = self.buf.as_ptr().addr();
+        let end = start + len;
+        crate::dcache::clean(buf + start..buf + end.min(self.buf.len()));
+        crate::dcache::clean(buf..buf + end.saturating_sub(self.buf.len()));
+    }
+
+    /// Appends `data` to the current frame at `write`, where the caller checked that at least
+    /// `data.len()` bytes are available before `read`.
+    #[cfg(not(feature = "word-write"))]
+    #[inline]
+    fn copy(&mut self, data: &[u8], write: usize) {
+        let len = data.len();
+        let buf: *mut u8 = self.buf.as_ptr().cast_mut().cast();
+
         // There are `ptr::copy_nonoverlapping` and `pointer::add` calls below.
         // The common safety arguments are:
         //
@@ -301,7 +1080,7 @@ impl Producer<'_> {
             // - First copy: data[0..pivot] -> buf[write..buf.len()]
             //   - src: pivot < len <= data.len() (since write + len > buf.len()
             //     implies len > buf.len() - write = pivot).
-            //   - dst: write < buf.len() by field invariant, and
+            //   - dst: write < buf.len() by the modulo above, and
             //     write + pivot = buf.len(), so dst is buf[write..buf.len()].
             unsafe { ptr::copy_nonoverlapping(data.as_ptr(), buf.add(write), pivot) };
             // SAFETY:
@@ -317,23 +1096,179 @@ impl Producer<'_> {
             // Non-wrapping case: the entire write fits before the end.
             // SAFETY:
             // - src: data[0..len] is valid since len <= data.len().
-            // - dst: buf[write..write+len]. write < buf.len() by field
-            //   invariant, and write + len <= buf.len() by the else branch
+            // - dst: buf[write..write+len]. write < buf.len() by the modulo
+            //   above, and write + len <= buf.len() by the else branch
             //   condition. len <= available ensures we don't write into
             //   consumer-owned memory.
             unsafe { ptr::copy_nonoverlapping(data.as_ptr(), buf.add(write), len) };
         }
 
+        self.pending += len;
+    }
+
+    /// Writes the staged word to `buf` at `start`.
+    ///
+    /// `start` must be a word boundary in the producer-owned part of `buf`, which
+    /// [`Producer::write`] reserves up to the end of the word.
+    #[cfg(feature = "word-write")]
+    #[inline]
+    fn store_stage(&self, start: usize) {
+        let word: *mut Word = self.buf[start..].as_ptr().cast_mut().cast();
+        // SAFETY: `init` checks that `buf` starts at a word boundary and holds whole words, so
+        // the word at `start` is aligned and inside `buf`. The caller guarantees that it is
+        // producer-owned, so the consumer does not access it.
+        unsafe { word.write_volatile(Word::from_ne_bytes(self.stage)) };
+    }
+
+    /// Finishes the current frame, making it visible to the consumer.
+    ///
+    /// If any part of the frame did not fit, the whole frame is discarded instead and
+    /// counted in the dropped frames counter. Returns `false` in that case.
+    #[inline]
+    pub fn commit(&mut self) -> bool {
+        let pending = core::mem::take(&mut self.pending);
+        if core::mem::take(&mut self.discard) {
+            critical_section::with(|_| {
+                // Relaxed: producer owns `dropped`, it is only informational for the consumer.
+                let dropped = self.header.load_meta(Meta::Dropped, Ordering::Relaxed);
+                self.header
+                    .store_meta(Meta::Dropped, dropped.wrapping_add(1), Ordering::Relaxed);
+                self.header.update_checksum();
+            });
+            return false;
+        }
+        if pending == 0 {
+            return true;
+        }
+
+        // Relaxed: producer owns `write`, no cross-thread synchronization needed.
+        let write = self.header.write.load(Ordering::Relaxed) as usize;
+
+        // Pad the last word, `write` reserved space for it.
+        #[cfg(feature = "word-write")]
+        let pending = {
+            let offset = pending % size_of::<Word>();
+            if offset != 0 {
+                let start = (write + pending - offset) % self.buf.len();
+                self.stage[offset..].fill(0);
+                self.store_stage(start);
+                #[cfg(feature = "dcache")]
+                self.clean_dcache(start, size_of::<Word>());
+            }
+            pending.next_multiple_of(size_of::<Word>())
+        };
+
         // Flush data before updating index. With 32-bit ECC, the index store may flush
         // immediately while data is still cached. This ensures the index never points
         // to uncommitted data.
         self.header.flush_ecc();
 
-        self.header.write.store(
-            (write.wrapping_add(len) % self.buf.len()) as u32,
-            Ordering::Release,
-        );
-        self.header.flush_ecc();
+        critical_section::with(|_| {
+            self.header.write.store(
+                (write.wrapping_add(pending) % self.buf.len()) as u32,
+                Ordering::Release,
+            );
+            self.header.update_checksum();
+        });
+        true
+    }
+
+    /// Returns whether a frame of `len` bytes fits in the buffer without discarding anything.
+    ///
+    /// Only meaningful between frames, when nothing is pending.
+    #[cfg(feature = "flash")]
+    pub(crate) fn fits(&self, len: usize) -> bool {
+        #[cfg(feature = "word-write")]
+        let len = len.next_multiple_of(size_of::<Word>());
+        // Relaxed: producer owns `write`.
+        let write = self.header.write.load(Ordering::Relaxed) as usize;
+        // Relaxed: stale `read` is safe (underestimates available space).
+        let read = self.header.read.load(Ordering::Relaxed);
+        #[cfg(feature = "overwrite")]
+        let read = read & !READ_LOCK;
+        len <= self.available(read as usize, write)
+    }
+
+    /// Drops the current frame without counting it as dropped.
+    pub(crate) fn abort(&mut self) {
+        self.pending = 0;
+        self.discard = false;
+    }
+
+    /// Returns the number of frames dropped because they did not fit in the buffer.
+    #[inline]
+    pub fn dropped_frames(&self) -> u32 {
+        // Relaxed: producer owns `dropped`.
+        self.header.load_meta(Meta::Dropped, Ordering::Relaxed)
+    }
+
+    /// Returns the minimum level of the frames to store, see [`Consumer::set_log_level`].
+    #[inline]
+    pub(crate) fn log_level(&self) -> LogLevel {
+        // Relaxed: only written by the consumer in a critical section, and read by the logger
+        // in one as well.
+        LogLevel::from_bits(self.header.load_meta(Meta::LogLevel, Ordering::Relaxed))
+    }
+
+    /// Returns the number of times the buffer was recovered since it was initialized.
+    #[cfg(feature = "flash")]
+    #[inline]
+    pub(crate) fn boot_count(&self) -> u32 {
+        // Relaxed: only written during recovery, before the producer is created.
+        self.header.load_meta(Meta::BootCount, Ordering::Relaxed)
+    }
+
+    /// Returns the start and length of the data the consumer has not released yet.
+    #[cfg(any(feature = "rtt-replay", feature = "flash"))]
+    pub(crate) fn unreleased(&self) -> (usize, usize) {
+        // Relaxed: the consumer and the reclaim in `write` only move `read` in critical
+        // sections, which the caller holds or which are not running yet.
+        let read = self.header.read.load(Ordering::Relaxed);
+        #[cfg(feature = "overwrite")]
+        let read = read & !READ_LOCK;
+        let read = read as usize;
+        let write = self.header.write.load(Ordering::Relaxed) as usize;
+        (read, (write + self.buf.len() - read) % self.buf.len())
+    }
+
+    /// Returns the part of the `len` bytes at `start` that the consumer has not released yet,
+    /// or `None` once it has released all of them.
+    ///
+    /// Must be called from within a critical section, and at least once per committed frame
+    /// for as long as it returns `Some`. Otherwise the consumer could release the range and
+    /// come back around the ring to it between two calls.
+    #[cfg(any(feature = "rtt-replay", feature = "flash"))]
+    pub(crate) fn unreleased_part(&self, start: usize, len: usize) -> Option<(&[u8], &[u8])> {
+        let size = self.buf.len();
+        // Relaxed: the consumer and the reclaim in `write` only move `read` in critical
+        // sections, so it is stable while the caller holds one.
+        let read = self.header.read.load(Ordering::Relaxed);
+        #[cfg(feature = "overwrite")]
+        let read = read & !READ_LOCK;
+        let read = read as usize;
+
+        let released = (read + size - start) % size;
+        if released >= len {
+            return None;
+        }
+        let end = (start + len) % size;
+        let (len1, len2) = if end <= read {
+            (size - read, end)
+        } else {
+            (end - read, 0)
+        };
+
+        let buf: *const u8 = self.buf.as_ptr().cast();
+        // SAFETY: `read..end` (modulo wrapping) lies within the consumer-owned part of `buf`,
+        // which is initialized, see `Consumer::read`. The consumer only reads this memory, and
+        // the producer does not write it until `read` moves past it, which cannot happen
+        // while the caller holds the critical section the returned slices are used in.
+        unsafe {
+            Some((
+                slice::from_raw_parts(buf.add(read), len1),
+                slice::from_raw_parts(buf, len2),
+            ))
+        }
     }
 }
 
@@ -344,9 +1279,12 @@ impl Consumer<'_> {
         // Acquire: synchronizes with producer's Release store to see written data.
         let write = self.header.write.load(Ordering::Acquire) as usize;
         // Relaxed: consumer owns `read`, no cross-thread synchronization needed.
-        let read = self.header.read.load(Ordering::Relaxed) as usize;
+        let read = self.header.read.load(Ordering::Relaxed);
+        // A grant leaked with `mem::forget` leaves the lock bit set.
+        #[cfg(feature = "overwrite")]
+        let read = read & !READ_LOCK;
 
-        write == read
+        write == read as usize
     }
 
     /// Read data from the buffer.
@@ -357,10 +1295,21 @@ impl Consumer<'_> {
     #[inline]
     #[must_use]
     pub fn read(&mut self) -> GrantR<'_, '_> {
-        // Acquire: synchronizes with producer's Release store, ensuring we see the written data.
-        let write = self.header.write.load(Ordering::Acquire) as usize;
         // Relaxed: consumer owns `read`, no cross-thread synchronization needed.
+        #[cfg(not(feature = "overwrite"))]
         let read = self.header.read.load(Ordering::Relaxed) as usize;
+        // Acquire: the producer may have advanced `read` to reclaim space. Setting the lock
+        // bit in the same critical section as the load stops it from doing so until the grant
+        // is dropped. A lock bit that is already set is stale: grants borrow the consumer
+        // mutably, so it was left by a grant leaked with `mem::forget`.
+        #[cfg(feature = "overwrite")]
+        let read = critical_section::with(|_| {
+            let read = self.header.read.load(Ordering::Acquire) & !READ_LOCK;
+            self.header.read.store(read | READ_LOCK, Ordering::Relaxed);
+            read as usize
+        });
+        // Acquire: synchronizes with producer's Release store, ensuring we see the written data.
+        let write = self.header.write.load(Ordering::Acquire) as usize;
         let buf: *mut u8 = self.buf.as_ptr().cast_mut().cast();
 
         let (len1, len2) = if write < read {
@@ -427,30 +1376,128 @@ impl Consumer<'_> {
         }
     }
 
-    #[cfg(feature = "async-await")]
-    /// Waits until there is data in the [`Consumer`].
-    pub async fn wait_for_data(&mut self) {
-        core::future::poll_fn(|cx| {
-            super::logger::WAKER.register(cx.waker());
+    /// Peeks at the complete frames in the buffer, without consuming them.
+    ///
+    /// Use [`Iterator::rev`] to start from the most recent frame, e.g. to show the last few log
+    /// lines on a display. The frames are left in the buffer, to be consumed later with
+    /// [`Consumer::read`].
+    #[inline]
+    pub fn peek_frames(&mut self) -> PeekFrames<'_, '_> {
+        let grant = self.read();
+        let frames = Frames::new(grant.slice1, grant.slice2);
+        PeekFrames::new(grant, frames)
+    }
 
-            if self.is_empty() {
-                core::task::Poll::Pending
-            } else {
-                core::task::Poll::Ready(())
-            }
-        })
-        .await
+    /// Returns the number of frames dropped because they did not fit in the buffer.
+    ///
+    /// The counter persists across resets and wraps on overflow. It is only cleared when the
+    /// buffer is reinitialized. With the `overwrite` feature, old frames discarded to make
+    /// room for new ones are not counted.
+    #[inline]
+    pub fn dropped_frames(&self) -> u32 {
+        // Relaxed: this is only informational, no data is accessed based on it.
+        self.header.load_meta(Meta::Dropped, Ordering::Relaxed)
     }
-}
 
-/// A read grant providing access to buffered data.
-///
-/// Obtained from [`Consumer::read`]. The grant provides a slice of available data
-/// via [`GrantR::buf`]. When done reading, call [`GrantR::release`] to mark bytes
-/// as consumed and free space for new writes.
-///
-/// If the grant is dropped without calling `release`, no data is consumed.
-pub struct GrantR<'a, 'c> {
+    /// Moves the newest complete frames that fit in `buf` out of the buffer, returning the
+    /// number of bytes copied.
+    ///
+    /// All data in the buffer is released, including older frames that did not fit.
+    pub(crate) fn drain_into(&mut self, buf: &mut [u8]) -> usize {
+        let grant = self.read();
+        let mut frames = grant.frames();
+        for _ in &mut frames {}
+        let end = frames.consumed();
+
+        // Skip the oldest frames until the rest fits.
+        let mut frames = grant.frames();
+        let mut start = 0;
+        while end - start > buf.len() && frames.next().is_some() {
+            start = frames.consumed();
+        }
+
+        let (buf1, buf2) = grant.bufs();
+        let split = buf1.len();
+        let src1 = &buf1[start.min(split)..end.min(split)];
+        let src2 = &buf2[start.max(split) - split..end.max(split) - split];
+        buf[..src1.len()].copy_from_slice(src1);
+        buf[src1.len()..end - start].copy_from_slice(src2);
+        grant.release_all();
+        end - start
+    }
+
+    /// Returns the minimum level of the frames stored by the logger.
+    #[inline]
+    pub fn log_level(&self) -> LogLevel {
+        // Relaxed: only written by this consumer.
+        LogLevel::from_bits(self.header.load_meta(Meta::LogLevel, Ordering::Relaxed))
+    }
+
+    /// Sets the minimum level of the frames stored by the logger.
+    ///
+    /// Frames below `level` are no longer stored in the buffer, but are still sent to RTT.
+    /// The level is persisted, so it also applies after a reset, until the buffer is
+    /// reinitialized. A reinitialized buffer stores all levels.
+    pub fn set_log_level(&mut self, level: LogLevel) {
+        critical_section::with(|_| {
+            // Relaxed: the logger reads the level in a critical section.
+            self.header
+                .store_meta(Meta::LogLevel, level.to_bits(), Ordering::Relaxed);
+            self.header.update_checksum();
+        });
+    }
+
+    /// Returns the number of times the buffer was recovered since it was initialized.
+    #[inline]
+    pub(crate) fn boot_count(&self) -> u32 {
+        // Relaxed: only written during recovery, before the consumer is created.
+        self.header.load_meta(Meta::BootCount, Ordering::Relaxed)
+    }
+
+    /// Stores the reset reason passed to this run, which tells why the previous run ended.
+    #[inline]
+    pub(crate) fn set_reset_reason(&mut self, reason: u32) {
+        // Relaxed: only accessed during initialization.
+        self.header
+            .store_meta(Meta::ResetReason, reason, Ordering::Relaxed);
+        self.header.update_checksum();
+    }
+
+    /// Stores the firmware identifier of this run, returning the one stored by the previous run.
+    #[inline]
+    pub(crate) fn swap_firmware_id(&mut self, id: u32) -> u32 {
+        // Relaxed: only accessed during initialization.
+        let previous = self.header.load_meta(Meta::FirmwareId, Ordering::Relaxed);
+        self.header
+            .store_meta(Meta::FirmwareId, id, Ordering::Relaxed);
+        self.header.update_checksum();
+        previous
+    }
+
+    #[cfg(feature = "async-await")]
+    /// Waits until there is data in the [`Consumer`].
+    pub async fn wait_for_data(&mut self) {
+        core::future::poll_fn(|cx| {
+            super::logger::WAKER.register(cx.waker());
+
+            if self.is_empty() {
+                core::task::Poll::Pending
+            } else {
+                core::task::Poll::Ready(())
+            }
+        })
+        .await
+    }
+}
+
+/// A read grant providing access to buffered data.
+///
+/// Obtained from [`Consumer::read`]. The grant provides a slice of available data
+/// via [`GrantR::buf`]. When done reading, call [`GrantR::release`] to mark bytes
+/// as consumed and free space for new writes.
+///
+/// If the grant is dropped without calling `release`, no data is consumed.
+pub struct GrantR<'a, 'c> {
     consumer: &'a Consumer<'c>,
     slice1: &'a [u8],
     slice2: &'a [u8],
@@ -461,7 +1508,8 @@ pub struct GrantR<'a, 'c> {
 // - Only one GrantR can exist at a time (Consumer::read takes &mut self)
 // - The slice is a regular &[u8] pointing to consumer-owned memory that the producer
 //   won't modify until release() updates the read pointer
-// - release() only performs atomic stores to header.read (and `_ecc_flush` for ECC)
+// - release() only performs atomic stores to header.read and header.checksum (and `_ecc_flush`
+//   for ECC), in a critical section
 // - The underlying UnsafeCell in Consumer::buf is not directly accessed through GrantR;
 //   the slice was materialized in Consumer::read before GrantR was created
 unsafe impl Send for GrantR<'_, '_> {}
@@ -481,11 +1529,17 @@ impl<'a, 'c> GrantR<'a, 'c> {
         } else {
             used - self.slice1.len()
         };
-        self.consumer
-            .header
-            .read
-            .store(new_read as u32, Ordering::Release);
-        self.consumer.header.flush_ecc();
+        critical_section::with(|_| {
+            // This also clears `READ_LOCK` with the `overwrite` feature.
+            self.consumer
+                .header
+                .read
+                .store(new_read as u32, Ordering::Release);
+            self.consumer.header.update_checksum();
+        });
+        // The lock is already cleared, skip `Drop`.
+        #[cfg(feature = "overwrite")]
+        core::mem::forget(self);
     }
 
     /// Finish the read, marking all bytes as used.
@@ -503,18 +1557,102 @@ impl<'a, 'c> GrantR<'a, 'c> {
     }
 }
 
+#[cfg(feature = "overwrite")]
+impl Drop for GrantR<'_, '_> {
+    fn drop(&mut self) {
+        // Clear `READ_LOCK` without consuming anything. In a critical section, as the producer
+        // checks `read` before storing to it in `reclaim`.
+        critical_section::with(|_| {
+            self.consumer
+                .header
+                .read
+                .store(self.original_read as u32, Ordering::Release);
+            self.consumer.header.flush_ecc();
+            self.consumer.header.clean_dcache();
+        });
+    }
+}
+
 #[cfg(test)]
 mod test {
 
     use super::*;
+    use core::mem::offset_of;
+
+    /// Memory for `recover_or_reinitialize`, standing in for a persist region.
+    #[repr(C, align(16))]
+    struct Region([u8; 128]);
+
+    fn recover(region: &mut Region) -> (Producer<'static>, Consumer<'static>, RecoveryStatus) {
+        let start = region.0.as_mut_ptr().expose_provenance();
+        // SAFETY: The region is aligned and larger than the header. The tests drop the returned
+        // producer and consumer before accessing `region` again.
+        unsafe { RingBuffer::recover_or_reinitialize(start..start + region.0.len()) }
+    }
+
+    #[cfg(feature = "word-write")]
+    const W: usize = size_of::<Word>();
+
+    /// Word-aligned test buffer, as `split` requires with `word-write`.
+    #[cfg(feature = "word-write")]
+    #[repr(C, align(8))]
+    struct WordBuf<const N: usize>([UnsafeCell<MaybeUninit<u8>>; N]);
+
+    #[cfg(feature = "word-write")]
+    impl<const N: usize> WordBuf<N> {
+        fn new() -> Self {
+            WordBuf([const { UnsafeCell::new(MaybeUninit::uninit()) }; N])
+        }
+    }
+
+    /// Test data with every byte repeated to fill a word.
+    #[cfg(feature = "word-write")]
+    struct Words {
+        bytes: [u8; 64],
+        len: usize,
+    }
+
+    #[cfg(feature = "word-write")]
+    impl core::ops::Deref for Words {
+        type Target = [u8];
+
+        fn deref(&self) -> &[u8] {
+            &self.bytes[..self.len]
+        }
+    }
+
+    #[cfg(feature = "word-write")]
+    fn words(bytes: &[u8]) -> Words {
+        let mut words = Words {
+            bytes: [0; 64],
+            len: bytes.len() * W,
+        };
+        for (word, &byte) in words.bytes.chunks_mut(W).zip(bytes) {
+            word.fill(byte);
+        }
+        words
+    }
+
+    /// Writes and commits a frame of `byte` that fills `len` words including its delimiter.
+    #[cfg(feature = "word-write")]
+    fn write_words(p: &mut Producer<'_>, byte: u8, len: usize) -> bool {
+        for _ in 1..len {
+            p.write(&[byte; W]);
+        }
+        p.write(&[byte; W][1..]);
+        p.write(&[0]);
+        p.commit()
+    }
 
     #[test]
+    #[cfg_attr(feature = "word-write", ignore = "writes bytes at unaligned positions")]
     fn touching_no_boundaries() {
         let mut b = RingBuffer::new(1, 1);
         let buf = &[const { UnsafeCell::new(MaybeUninit::uninit()) }; 4];
         // SAFETY: Test buffer is 4 bytes, well under i32::MAX / 4.
         let (mut p, mut c) = unsafe { b.split(buf) };
         p.write(&[1, 2]);
+        p.commit();
 
         let r = c.read();
         assert_eq!(r.bufs(), (&[1, 2][..], &[][..]));
@@ -524,12 +1662,31 @@ mod test {
     }
 
     #[test]
+    #[cfg(feature = "word-write")]
+    fn touching_no_boundaries_words() {
+        let mut b = RingBuffer::new(W as u32, W as u32);
+        let buf = WordBuf::<{ 4 * W }>::new();
+        // SAFETY: Test buffer is 4 words, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(&buf.0) };
+        p.write(&words(&[1, 2]));
+        p.commit();
+
+        let r = c.read();
+        assert_eq!(r.bufs(), (&words(&[1, 2])[..], &[][..]));
+        r.release(2 * W);
+        let r = c.read();
+        assert_eq!(r.bufs(), (&[][..], &[][..]));
+    }
+
+    #[test]
+    #[cfg_attr(feature = "word-write", ignore = "writes bytes at unaligned positions")]
     fn fill_simple() {
         let mut b = RingBuffer::new(0, 0);
         let buf = &[const { UnsafeCell::new(MaybeUninit::uninit()) }; 4];
         // SAFETY: Test buffer is 4 bytes, well under i32::MAX / 4.
         let (mut p, mut c) = unsafe { b.split(buf) };
         p.write(&[1, 2, 3]);
+        p.commit();
 
         let r = c.read();
         assert_eq!(r.bufs(), (&[1, 2, 3][..], &[][..]));
@@ -539,12 +1696,31 @@ mod test {
     }
 
     #[test]
+    #[cfg(feature = "word-write")]
+    fn fill_simple_words() {
+        let mut b = RingBuffer::new(0, 0);
+        let buf = WordBuf::<{ 4 * W }>::new();
+        // SAFETY: Test buffer is 4 words, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(&buf.0) };
+        p.write(&words(&[1, 2, 3]));
+        p.commit();
+
+        let r = c.read();
+        assert_eq!(r.bufs(), (&words(&[1, 2, 3])[..], &[][..]));
+        r.release(3 * W);
+        let r = c.read();
+        assert_eq!(r.bufs(), (&[][..], &[][..]));
+    }
+
+    #[test]
+    #[cfg_attr(feature = "word-write", ignore = "writes bytes at unaligned positions")]
     fn fill_crossing_end() {
         let mut b = RingBuffer::new(2, 2);
         let buf = &[const { UnsafeCell::new(MaybeUninit::uninit()) }; 4];
         // SAFETY: Test buffer is 4 bytes, well under i32::MAX / 4.
         let (mut p, mut c) = unsafe { b.split(buf) };
         p.write(&[1, 2, 3]);
+        p.commit();
 
         let r = c.read();
         assert_eq!(r.bufs(), (&[1, 2][..], &[3][..]));
@@ -557,12 +1733,34 @@ mod test {
     }
 
     #[test]
+    #[cfg(feature = "word-write")]
+    fn fill_crossing_end_words() {
+        let mut b = RingBuffer::new(2 * W as u32, 2 * W as u32);
+        let buf = WordBuf::<{ 4 * W }>::new();
+        // SAFETY: Test buffer is 4 words, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(&buf.0) };
+        p.write(&words(&[1, 2, 3]));
+        p.commit();
+
+        let r = c.read();
+        assert_eq!(r.bufs(), (&words(&[1, 2])[..], &words(&[3])[..]));
+        r.release(2 * W);
+        let r = c.read();
+        assert_eq!(r.bufs(), (&words(&[3])[..], &[][..]));
+        r.release(W);
+        let r = c.read();
+        assert_eq!(r.bufs(), (&[][..], &[][..]));
+    }
+
+    #[test]
+    #[cfg_attr(feature = "word-write", ignore = "writes bytes at unaligned positions")]
     fn release_crossing_end() {
         let mut b = RingBuffer::new(2, 2);
         let buf = &[const { UnsafeCell::new(MaybeUninit::uninit()) }; 4];
         // SAFETY: Test buffer is 4 bytes, well under i32::MAX / 4.
         let (mut p, mut c) = unsafe { b.split(buf) };
         p.write(&[1, 2, 3]);
+        p.commit();
 
         let r = c.read();
         assert_eq!(r.bufs(), (&[1, 2][..], &[3][..]));
@@ -572,12 +1770,31 @@ mod test {
     }
 
     #[test]
+    #[cfg(feature = "word-write")]
+    fn release_crossing_end_words() {
+        let mut b = RingBuffer::new(2 * W as u32, 2 * W as u32);
+        let buf = WordBuf::<{ 4 * W }>::new();
+        // SAFETY: Test buffer is 4 words, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(&buf.0) };
+        p.write(&words(&[1, 2, 3]));
+        p.commit();
+
+        let r = c.read();
+        assert_eq!(r.bufs(), (&words(&[1, 2])[..], &words(&[3])[..]));
+        r.release(3 * W);
+        let r = c.read();
+        assert_eq!(r.bufs(), (&[][..], &[][..]));
+    }
+
+    #[test]
+    #[cfg_attr(feature = "word-write", ignore = "writes bytes at unaligned positions")]
     fn underfill_crossing_end() {
         let mut b = RingBuffer::new(3, 3);
         let buf = &[const { UnsafeCell::new(MaybeUninit::uninit()) }; 4];
         // SAFETY: Test buffer is 4 bytes, well under i32::MAX / 4.
         let (mut p, mut c) = unsafe { b.split(buf) };
         p.write(&[1, 2]);
+        p.commit();
 
         let r = c.read();
         assert_eq!(r.bufs(), (&[1][..], &[2][..]));
@@ -589,6 +1806,26 @@ mod test {
         assert_eq!(r.bufs(), (&[][..], &[][..]));
     }
 
+    #[test]
+    #[cfg(feature = "word-write")]
+    fn underfill_crossing_end_words() {
+        let mut b = RingBuffer::new(3 * W as u32, 3 * W as u32);
+        let buf = WordBuf::<{ 4 * W }>::new();
+        // SAFETY: Test buffer is 4 words, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(&buf.0) };
+        p.write(&words(&[1, 2]));
+        p.commit();
+
+        let r = c.read();
+        assert_eq!(r.bufs(), (&words(&[1])[..], &words(&[2])[..]));
+        r.release(W);
+        let r = c.read();
+        assert_eq!(r.bufs(), (&words(&[2])[..], &[][..]));
+        r.release(W);
+        let r = c.read();
+        assert_eq!(r.bufs(), (&[][..], &[][..]));
+    }
+
     #[test]
     fn overfill() {
         let mut b = RingBuffer::new(0, 0);
@@ -596,21 +1833,24 @@ mod test {
         // SAFETY: Test buffer is 4 bytes, well under i32::MAX / 4.
         let (mut p, mut c) = unsafe { b.split(buf) };
         p.write(&[1, 2, 3, 4, 5, 6, 7]);
+        p.commit();
 
-        let r = c.read();
-        assert_eq!(r.bufs(), (&[1, 2, 3][..], &[][..]));
-        r.release(3);
+        // A frame that does not fit is discarded as a whole.
         let r = c.read();
         assert_eq!(r.bufs(), (&[][..], &[][..]));
+        r.release(0);
+        assert_eq!(c.dropped_frames(), 1);
     }
 
     #[test]
+    #[cfg_attr(feature = "word-write", ignore = "writes bytes at unaligned positions")]
     fn stop_at_end() {
         let mut b = RingBuffer::new(2, 2);
         let buf = &[const { UnsafeCell::new(MaybeUninit::uninit()) }; 4];
         // SAFETY: Test buffer is 4 bytes, well under i32::MAX / 4.
         let (mut p, mut c) = unsafe { b.split(buf) };
         p.write(&[1, 2]);
+        p.commit();
 
         let r = c.read();
         assert_eq!(r.bufs(), (&[1, 2][..], &[][..]));
@@ -620,12 +1860,31 @@ mod test {
     }
 
     #[test]
+    #[cfg(feature = "word-write")]
+    fn stop_at_end_words() {
+        let mut b = RingBuffer::new(2 * W as u32, 2 * W as u32);
+        let buf = WordBuf::<{ 4 * W }>::new();
+        // SAFETY: Test buffer is 4 words, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(&buf.0) };
+        p.write(&words(&[1, 2]));
+        p.commit();
+
+        let r = c.read();
+        assert_eq!(r.bufs(), (&words(&[1, 2])[..], &[][..]));
+        r.release(2 * W);
+        let r = c.read();
+        assert_eq!(r.bufs(), (&[][..], &[][..]));
+    }
+
+    #[test]
+    #[cfg_attr(feature = "word-write", ignore = "writes bytes at unaligned positions")]
     fn stop_before_end() {
         let mut b = RingBuffer::new(2, 2);
         let buf = &[const { UnsafeCell::new(MaybeUninit::uninit()) }; 4];
         // SAFETY: Test buffer is 4 bytes, well under i32::MAX / 4.
         let (mut p, mut c) = unsafe { b.split(buf) };
         p.write(&[1]);
+        p.commit();
 
         let r = c.read();
         assert_eq!(r.bufs(), (&[1][..], &[][..]));
@@ -635,12 +1894,31 @@ mod test {
     }
 
     #[test]
+    #[cfg(feature = "word-write")]
+    fn stop_before_end_words() {
+        let mut b = RingBuffer::new(2 * W as u32, 2 * W as u32);
+        let buf = WordBuf::<{ 4 * W }>::new();
+        // SAFETY: Test buffer is 4 words, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(&buf.0) };
+        p.write(&words(&[1]));
+        p.commit();
+
+        let r = c.read();
+        assert_eq!(r.bufs(), (&words(&[1])[..], &[][..]));
+        r.release(W);
+        let r = c.read();
+        assert_eq!(r.bufs(), (&[][..], &[][..]));
+    }
+
+    #[test]
+    #[cfg_attr(feature = "word-write", ignore = "writes bytes at unaligned positions")]
     fn zero_release() {
         let mut b = RingBuffer::new(2, 2);
         let buf = &[const { UnsafeCell::new(MaybeUninit::uninit()) }; 4];
         // SAFETY: Test buffer is 4 bytes, well under i32::MAX / 4.
         let (mut p, mut c) = unsafe { b.split(buf) };
         p.write(&[1, 2]);
+        p.commit();
 
         let r = c.read();
         assert_eq!(r.bufs(), (&[1, 2][..], &[][..]));
@@ -650,12 +1928,31 @@ mod test {
     }
 
     #[test]
+    #[cfg(feature = "word-write")]
+    fn zero_release_words() {
+        let mut b = RingBuffer::new(2 * W as u32, 2 * W as u32);
+        let buf = WordBuf::<{ 4 * W }>::new();
+        // SAFETY: Test buffer is 4 words, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(&buf.0) };
+        p.write(&words(&[1, 2]));
+        p.commit();
+
+        let r = c.read();
+        assert_eq!(r.bufs(), (&words(&[1, 2])[..], &[][..]));
+        r.release(0);
+        let r = c.read();
+        assert_eq!(r.bufs(), (&words(&[1, 2])[..], &[][..]));
+    }
+
+    #[test]
+    #[cfg_attr(feature = "word-write", ignore = "writes bytes at unaligned positions")]
     fn partial_release() {
         let mut b = RingBuffer::new(2, 2);
         let buf = &[const { UnsafeCell::new(MaybeUninit::uninit()) }; 4];
         // SAFETY: Test buffer is 4 bytes, well under i32::MAX / 4.
         let (mut p, mut c) = unsafe { b.split(buf) };
         p.write(&[1, 2]);
+        p.commit();
 
         let r = c.read();
         assert_eq!(r.bufs(), (&[1, 2][..], &[][..]));
@@ -663,4 +1960,1008 @@ mod test {
         let r = c.read();
         assert_eq!(r.bufs(), (&[2][..], &[][..]));
     }
+
+    #[test]
+    #[cfg(feature = "word-write")]
+    fn partial_release_words() {
+        let mut b = RingBuffer::new(2 * W as u32, 2 * W as u32);
+        let buf = WordBuf::<{ 4 * W }>::new();
+        // SAFETY: Test buffer is 4 words, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(&buf.0) };
+        p.write(&words(&[1, 2]));
+        p.commit();
+
+        let r = c.read();
+        assert_eq!(r.bufs(), (&words(&[1, 2])[..], &[][..]));
+        r.release(W);
+        let r = c.read();
+        assert_eq!(r.bufs(), (&words(&[2])[..], &[][..]));
+    }
+
+    #[test]
+    #[cfg_attr(feature = "word-write", ignore = "writes bytes at unaligned positions")]
+    fn uncommitted_frame_is_hidden() {
+        let mut b = RingBuffer::new(0, 0);
+        let buf = &[const { UnsafeCell::new(MaybeUninit::uninit()) }; 4];
+        // SAFETY: Test buffer is 4 bytes, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(buf) };
+        p.write(&[1]);
+        p.write(&[2]);
+
+        assert_eq!(c.read().bufs(), (&[][..], &[][..]));
+        p.commit();
+        let r = c.read();
+        assert_eq!(r.bufs(), (&[1, 2][..], &[][..]));
+    }
+
+    #[test]
+    #[cfg(feature = "word-write")]
+    fn uncommitted_frame_is_hidden_words() {
+        let mut b = RingBuffer::new(0, 0);
+        let buf = WordBuf::<{ 4 * W }>::new();
+        // SAFETY: Test buffer is 4 words, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(&buf.0) };
+        p.write(&words(&[1]));
+        p.write(&words(&[2]));
+
+        assert_eq!(c.read().bufs(), (&[][..], &[][..]));
+        p.commit();
+        let r = c.read();
+        assert_eq!(r.bufs(), (&words(&[1, 2])[..], &[][..]));
+    }
+
+    #[test]
+    #[cfg_attr(feature = "word-write", ignore = "writes bytes at unaligned positions")]
+    fn discard_partial_frame() {
+        let mut b = RingBuffer::new(0, 0);
+        let buf = &[const { UnsafeCell::new(MaybeUninit::uninit()) }; 4];
+        // SAFETY: Test buffer is 4 bytes, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(buf) };
+        p.write(&[1, 2]);
+        p.write(&[3, 4]);
+        assert!(!p.commit());
+
+        assert_eq!(c.read().bufs(), (&[][..], &[][..]));
+
+        // The next frame starts fresh.
+        p.write(&[5]);
+        assert!(p.commit());
+        assert_eq!(c.dropped_frames(), 1);
+        let r = c.read();
+        assert_eq!(r.bufs(), (&[5][..], &[][..]));
+    }
+
+    #[test]
+    #[cfg(feature = "word-write")]
+    fn discard_partial_frame_words() {
+        let mut b = RingBuffer::new(0, 0);
+        let buf = WordBuf::<{ 4 * W }>::new();
+        // SAFETY: Test buffer is 4 words, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(&buf.0) };
+        p.write(&words(&[1, 2]));
+        p.write(&words(&[3, 4]));
+        assert!(!p.commit());
+
+        assert_eq!(c.read().bufs(), (&[][..], &[][..]));
+
+        // The next frame starts fresh.
+        p.write(&words(&[5]));
+        assert!(p.commit());
+        assert_eq!(c.dropped_frames(), 1);
+        let r = c.read();
+        assert_eq!(r.bufs(), (&words(&[5])[..], &[][..]));
+    }
+
+    #[test]
+    #[cfg_attr(feature = "word-write", ignore = "writes bytes at unaligned positions")]
+    #[cfg(feature = "overwrite")]
+    fn overwrite_oldest_frame() {
+        let mut b = RingBuffer::new(0, 0);
+        let buf = &[const { UnsafeCell::new(MaybeUninit::uninit()) }; 8];
+        // SAFETY: Test buffer is 8 bytes, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(buf) };
+        p.write(&[1, 0]);
+        p.commit();
+        p.write(&[2, 2, 0]);
+        p.commit();
+        p.write(&[3, 3, 0]);
+        p.commit();
+
+        let r = c.read();
+        assert_eq!(r.bufs(), (&[2, 2, 0, 3, 3, 0][..], &[][..]));
+        r.release_all();
+        let r = c.read();
+        assert_eq!(r.bufs(), (&[][..], &[][..]));
+    }
+
+    #[test]
+    #[cfg(feature = "word-write")]
+    #[cfg(feature = "overwrite")]
+    fn overwrite_oldest_frame_words() {
+        let mut b = RingBuffer::new(0, 0);
+        let buf = WordBuf::<{ 4 * W }>::new();
+        // SAFETY: Test buffer is 4 words, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(&buf.0) };
+        write_words(&mut p, 1, 1);
+        write_words(&mut p, 2, 1);
+        write_words(&mut p, 3, 2);
+
+        let r = c.read();
+        assert_eq!(r.bufs().0.len(), 3 * W);
+        let mut frames = r.frames();
+        assert_eq!(frames.next().unwrap().bufs(), (&[2; W - 1][..], &[][..]));
+        assert_eq!(
+            frames.next().unwrap().bufs(),
+            (&[3; 2 * W - 1][..], &[][..])
+        );
+        assert_eq!(frames.next(), None);
+        r.release_all();
+        let r = c.read();
+        assert_eq!(r.bufs(), (&[][..], &[][..]));
+    }
+
+    #[test]
+    #[cfg_attr(feature = "word-write", ignore = "writes bytes at unaligned positions")]
+    #[cfg(feature = "overwrite")]
+    fn overwrite_without_delimiter() {
+        let mut b = RingBuffer::new(0, 0);
+        let buf = &[const { UnsafeCell::new(MaybeUninit::uninit()) }; 8];
+        // SAFETY: Test buffer is 8 bytes, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(buf) };
+        p.write(&[1, 1, 1, 1, 1]);
+        p.commit();
+        p.write(&[2, 2, 0]);
+        p.commit();
+
+        let r = c.read();
+        assert_eq!(r.bufs(), (&[2, 2, 0][..], &[][..]));
+    }
+
+    #[test]
+    #[cfg(feature = "word-write")]
+    #[cfg(feature = "overwrite")]
+    fn overwrite_without_delimiter_words() {
+        let mut b = RingBuffer::new(0, 0);
+        let buf = WordBuf::<{ 4 * W }>::new();
+        // SAFETY: Test buffer is 4 words, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(&buf.0) };
+        p.write(&[1; 2 * W]);
+        p.commit();
+        write_words(&mut p, 2, 2);
+
+        let r = c.read();
+        assert_eq!(r.bufs().0.len(), 2 * W);
+        let mut frames = r.frames();
+        assert_eq!(
+            frames.next().unwrap().bufs(),
+            (&[2; 2 * W - 1][..], &[][..])
+        );
+        assert_eq!(frames.next(), None);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "word-write", ignore = "writes bytes at unaligned positions")]
+    #[cfg(feature = "overwrite")]
+    fn overwrite_forgotten_grant() {
+        let mut b = RingBuffer::new(0, 0);
+        let buf = &[const { UnsafeCell::new(MaybeUninit::uninit()) }; 16];
+        // SAFETY: Test buffer is 16 bytes, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(buf) };
+        p.write(&[1, 1, 1, 0]);
+        p.commit();
+        p.write(&[2, 2, 2, 0]);
+        p.commit();
+
+        // Leaves the lock bit set.
+        core::mem::forget(c.read());
+        assert!(!c.is_empty());
+        let r = c.read();
+        assert_eq!(r.bufs(), (&[1, 1, 1, 0, 2, 2, 2, 0][..], &[][..]));
+        r.release(4);
+
+        // Reclaiming works again once the grant is released.
+        p.write(&[3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 0]);
+        assert!(p.commit());
+        let r = c.read();
+        assert_eq!(r.bufs(), (&[3; 8][..], &[3, 3, 3, 0][..]));
+        core::mem::forget(r);
+        c.read().release_all();
+        assert!(c.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "word-write")]
+    #[cfg(feature = "overwrite")]
+    fn overwrite_forgotten_grant_words() {
+        let mut b = RingBuffer::new(0, 0);
+        let buf = WordBuf::<{ 4 * W }>::new();
+        // SAFETY: Test buffer is 4 words, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(&buf.0) };
+        write_words(&mut p, 1, 1);
+        write_words(&mut p, 2, 1);
+
+        // Leaves the lock bit set.
+        core::mem::forget(c.read());
+        assert!(!c.is_empty());
+        let r = c.read();
+        assert_eq!(r.bufs().0.len(), 2 * W);
+        r.release(W);
+
+        // Reclaiming works again once the grant is released.
+        assert!(write_words(&mut p, 3, 3));
+        let r = c.read();
+        let mut frames = r.frames();
+        assert_eq!(
+            frames.next().unwrap().bufs(),
+            (&[3; 2 * W][..], &[3; W - 1][..])
+        );
+        assert_eq!(frames.next(), None);
+        core::mem::forget(r);
+        c.read().release_all();
+        assert!(c.is_empty());
+    }
+
+    #[test]
+    #[cfg_attr(feature = "word-write", ignore = "writes bytes at unaligned positions")]
+    #[cfg(feature = "overwrite")]
+    fn overwrite_blocked_by_grant() {
+        let mut b = RingBuffer::new(0, 0);
+        let buf = &[const { UnsafeCell::new(MaybeUninit::uninit()) }; 8];
+        // SAFETY: Test buffer is 8 bytes, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(buf) };
+        p.write(&[1, 0]);
+        p.commit();
+        p.write(&[2, 2, 0]);
+        p.commit();
+
+        let r = c.read();
+        // The grant covers all data, so nothing may be reclaimed and the frame is discarded.
+        p.write(&[3, 3, 0]);
+        p.commit();
+        assert_eq!(r.bufs(), (&[1, 0, 2, 2, 0][..], &[][..]));
+        drop(r);
+
+        let r = c.read();
+        assert_eq!(r.bufs(), (&[1, 0, 2, 2, 0][..], &[][..]));
+        r.release_all();
+
+        // Releasing unlocked `read`, so old frames can be reclaimed again.
+        p.write(&[4, 0]);
+        p.commit();
+        p.write(&[5, 5, 5, 0]);
+        p.commit();
+        p.write(&[6, 6, 0]);
+        p.commit();
+        let r = c.read();
+        assert_eq!(r.bufs(), (&[5][..], &[5, 5, 0, 6, 6, 0][..]));
+    }
+
+    #[test]
+    #[cfg(feature = "word-write")]
+    #[cfg(feature = "overwrite")]
+    fn overwrite_blocked_by_grant_words() {
+        let mut b = RingBuffer::new(0, 0);
+        let buf = WordBuf::<{ 4 * W }>::new();
+        // SAFETY: Test buffer is 4 words, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(&buf.0) };
+        write_words(&mut p, 1, 1);
+        write_words(&mut p, 2, 1);
+
+        let r = c.read();
+        // The grant covers all data, so nothing may be reclaimed and the frame is discarded.
+        assert!(!write_words(&mut p, 3, 2));
+        assert_eq!(r.bufs().0.len(), 2 * W);
+        drop(r);
+
+        let r = c.read();
+        assert_eq!(r.bufs().0.len(), 2 * W);
+        r.release_all();
+
+        // Releasing unlocked `read`, so old frames can be reclaimed again.
+        write_words(&mut p, 4, 1);
+        write_words(&mut p, 5, 2);
+        write_words(&mut p, 6, 1);
+        let r = c.read();
+        let mut frames = r.frames();
+        assert_eq!(
+            frames.next().unwrap().bufs(),
+            (&[5; W][..], &[5; W - 1][..])
+        );
+        assert_eq!(frames.next().unwrap().bufs(), (&[][..], &[6; W - 1][..]));
+        assert_eq!(frames.next(), None);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "word-write", ignore = "writes bytes at unaligned positions")]
+    fn peek_frames() {
+        let mut b = RingBuffer::new(0, 0);
+        let buf = &[const { UnsafeCell::new(MaybeUninit::uninit()) }; 10];
+        // SAFETY: Test buffer is 10 bytes, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(buf) };
+        // Leading separator, as written by the encoder before the first frame.
+        p.write(&[0, 1, 1, 0]);
+        p.commit();
+        p.write(&[2, 2, 2, 0]);
+        p.commit();
+        // Incomplete frame.
+        p.write(&[3]);
+        p.commit();
+
+        {
+            let mut frames = c.peek_frames();
+            assert_eq!(
+                frames.next_back().unwrap().bufs(),
+                (&[2, 2, 2][..], &[][..])
+            );
+            assert_eq!(frames.next().unwrap().bufs(), (&[1, 1][..], &[][..]));
+            assert_eq!(frames.next(), None);
+            assert_eq!(frames.next_back(), None);
+        }
+
+        // Nothing was consumed.
+        let r = c.read();
+        assert_eq!(r.bufs(), (&[0, 1, 1, 0, 2, 2, 2, 0, 3][..], &[][..]));
+        let mut frames = r.frames();
+        frames.next();
+        let consumed = frames.consumed();
+        r.release(consumed);
+
+        // Completes the frame across the end of the ring.
+        p.write(&[4, 4, 0]);
+        p.commit();
+        let mut frames = c.peek_frames().rev();
+        assert_eq!(frames.next().unwrap().bufs(), (&[3, 4][..], &[4][..]));
+        assert_eq!(frames.next().unwrap().bufs(), (&[2, 2, 2][..], &[][..]));
+        assert_eq!(frames.next(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "word-write")]
+    fn peek_frames_words() {
+        let mut b = RingBuffer::new(W as u32, W as u32);
+        let buf = WordBuf::<{ 5 * W }>::new();
+        // SAFETY: Test buffer is 5 words, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(&buf.0) };
+        // Leading separator, as written by the encoder before the first frame.
+        p.write(&[0, 1, 1, 0]);
+        p.commit();
+        write_words(&mut p, 2, 1);
+        // Incomplete frame, without padding.
+        p.write(&[3; W]);
+        p.commit();
+
+        {
+            let mut frames = c.peek_frames();
+            assert_eq!(
+                frames.next_back().unwrap().bufs(),
+                (&[2; W - 1][..], &[][..])
+            );
+            assert_eq!(frames.next().unwrap().bufs(), (&[1, 1][..], &[][..]));
+            assert_eq!(frames.next(), None);
+            assert_eq!(frames.next_back(), None);
+        }
+
+        // Nothing was consumed.
+        let r = c.read();
+        assert_eq!(r.bufs().0.len(), 3 * W);
+        let mut frames = r.frames();
+        frames.next();
+        let consumed = frames.consumed();
+        r.release(consumed);
+
+        // Completes the frame across the end of the ring.
+        p.write(&[4; W]);
+        p.write(&[4, 0]);
+        p.commit();
+        let mut frames = c.peek_frames().rev();
+        let (first, second) = frames.next().unwrap().bufs();
+        assert_eq!((&first[..W], &first[W..]), (&[3; W][..], &[4; W][..]));
+        assert_eq!(second, [4]);
+        assert_eq!(frames.next().unwrap().bufs(), (&[2; W - 1][..], &[][..]));
+        assert_eq!(frames.next(), None);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "word-write", ignore = "writes bytes at unaligned positions")]
+    fn frames_prefix() {
+        let mut b = RingBuffer::new(6, 6);
+        let buf = &[const { UnsafeCell::new(MaybeUninit::uninit()) }; 10];
+        // SAFETY: Test buffer is 10 bytes, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(buf) };
+        p.write(&[1, 0]);
+        p.commit();
+        p.write(&[2, 2, 2, 0]);
+        p.commit();
+        p.write(&[3, 0]);
+        p.commit();
+
+        let r = c.read();
+        assert_eq!(r.frames_prefix(0), (&[][..], &[][..]));
+        assert_eq!(r.frames_prefix(5), (&[1, 0][..], &[][..]));
+        assert_eq!(r.frames_prefix(6), (&[1, 0, 2, 2][..], &[2, 0][..]));
+        assert_eq!(r.frames_prefix(100), (&[1, 0, 2, 2][..], &[2, 0, 3, 0][..]));
+
+        assert_eq!(r.release_frames(2), 2);
+        let r = c.read();
+        assert_eq!(r.bufs(), (&[3, 0][..], &[][..]));
+        assert_eq!(r.release_frames(2), 1);
+        assert!(c.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "word-write")]
+    fn frames_prefix_words() {
+        let mut b = RingBuffer::new(3 * W as u32, 3 * W as u32);
+        let buf = WordBuf::<{ 5 * W }>::new();
+        // SAFETY: Test buffer is 5 words, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(&buf.0) };
+        write_words(&mut p, 1, 1);
+        write_words(&mut p, 2, 2);
+        write_words(&mut p, 3, 1);
+
+        let r = c.read();
+        let lens = |(first, second): (&[u8], &[u8])| (first.len(), second.len());
+        assert_eq!(lens(r.frames_prefix(0)), (0, 0));
+        assert_eq!(lens(r.frames_prefix(3 * W - 1)), (W, 0));
+        assert_eq!(lens(r.frames_prefix(3 * W)), (2 * W, W));
+        assert_eq!(lens(r.frames_prefix(100)), (2 * W, 2 * W));
+
+        assert_eq!(r.release_frames(2), 2);
+        let r = c.read();
+        assert_eq!(
+            r.frames().next().unwrap().bufs(),
+            (&[3; W - 1][..], &[][..])
+        );
+        assert_eq!(r.release_frames(2), 1);
+        assert!(c.is_empty());
+    }
+
+    #[test]
+    #[cfg_attr(feature = "word-write", ignore = "writes bytes at unaligned positions")]
+    fn drain_into() {
+        let mut b = RingBuffer::new(6, 6);
+        let buf = &[const { UnsafeCell::new(MaybeUninit::uninit()) }; 10];
+        // SAFETY: Test buffer is 10 bytes, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(buf) };
+        p.write(&[1, 0]);
+        p.commit();
+        p.write(&[2, 2, 2, 0]);
+        p.commit();
+        p.write(&[3, 0]);
+        p.commit();
+
+        // The oldest frame does not fit.
+        let mut snapshot = [0; 7];
+        assert_eq!(c.drain_into(&mut snapshot), 6);
+        assert_eq!(snapshot[..6], [2, 2, 2, 0, 3, 0]);
+        assert!(c.is_empty());
+
+        p.write(&[4, 4, 0]);
+        p.commit();
+        let mut snapshot = [0; 2];
+        assert_eq!(c.drain_into(&mut snapshot), 0);
+        assert!(c.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "word-write")]
+    fn drain_into_words() {
+        let mut b = RingBuffer::new(3 * W as u32, 3 * W as u32);
+        let buf = WordBuf::<{ 5 * W }>::new();
+        // SAFETY: Test buffer is 5 words, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(&buf.0) };
+        write_words(&mut p, 1, 1);
+        write_words(&mut p, 2, 2);
+        write_words(&mut p, 3, 1);
+
+        // The oldest frame does not fit.
+        let mut snapshot = [0xff; 3 * W + 1];
+        assert_eq!(c.drain_into(&mut snapshot), 3 * W);
+        assert_eq!(snapshot[..2 * W - 1], [2; 2 * W - 1]);
+        assert_eq!(snapshot[2 * W - 1], 0);
+        assert_eq!(snapshot[2 * W..3 * W - 1], [3; W - 1]);
+        assert!(c.is_empty());
+
+        write_words(&mut p, 4, 2);
+        let mut snapshot = [0; W];
+        assert_eq!(c.drain_into(&mut snapshot), 0);
+        assert!(c.is_empty());
+    }
+
+    #[test]
+    #[cfg_attr(feature = "word-write", ignore = "writes bytes at unaligned positions")]
+    #[cfg(any(feature = "rtt-replay", feature = "flash"))]
+    fn unreleased_part() {
+        let mut b = RingBuffer::new(6, 6);
+        let buf = &[const { UnsafeCell::new(MaybeUninit::uninit()) }; 10];
+        // SAFETY: Test buffer is 10 bytes, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(buf) };
+        p.write(&[1, 1, 0]);
+        p.commit();
+        p.write(&[2, 2, 0]);
+        p.commit();
+
+        let (start, len) = p.unreleased();
+        assert_eq!((start, len), (6, 6));
+        assert_eq!(
+            p.unreleased_part(start, len),
+            Some((&[1, 1, 0, 2][..], &[2, 0][..]))
+        );
+
+        p.write(&[3, 0]);
+        p.commit();
+        c.read().release(3);
+        assert_eq!(p.unreleased_part(start, len), Some((&[2][..], &[2, 0][..])));
+        c.read().release(1);
+        assert_eq!(p.unreleased_part(start, len), Some((&[2, 0][..], &[][..])));
+
+        // The frame written after `unreleased` is not part of the range.
+        c.read().release(2);
+        assert_eq!(p.unreleased_part(start, len), None);
+    }
+
+    #[test]
+    #[cfg(feature = "word-write")]
+    #[cfg(any(feature = "rtt-replay", feature = "flash"))]
+    fn unreleased_part_words() {
+        let mut b = RingBuffer::new(3 * W as u32, 3 * W as u32);
+        let buf = WordBuf::<{ 5 * W }>::new();
+        // SAFETY: Test buffer is 5 words, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(&buf.0) };
+        write_words(&mut p, 1, 1);
+        write_words(&mut p, 2, 2);
+
+        let (start, len) = p.unreleased();
+        assert_eq!((start, len), (3 * W, 3 * W));
+        let lens = |part: Option<(&[u8], &[u8])>| part.map(|(a, b)| (a.len(), b.len()));
+        assert_eq!(lens(p.unreleased_part(start, len)), Some((2 * W, W)));
+
+        write_words(&mut p, 3, 1);
+        c.read().release(W);
+        assert_eq!(lens(p.unreleased_part(start, len)), Some((W, W)));
+        c.read().release(W);
+        let (first, second) = p.unreleased_part(start, len).unwrap();
+        assert_eq!(
+            (&first[..W - 1], &first[W - 1..]),
+            (&[2; W - 1][..], &[0][..])
+        );
+        assert_eq!(second, []);
+
+        // The frame written after `unreleased` is not part of the range.
+        c.read().release(W);
+        assert_eq!(p.unreleased_part(start, len), None);
+    }
+
+    /// Writes and commits `body` as the logger does with `frame-crc` enabled.
+    #[cfg(feature = "frame-crc")]
+    fn write_frame(p: &mut Producer<'_>, body: &[u8]) {
+        p.write(body);
+        p.write(&crate::frame::trailer(crate::crc::crc32(body)));
+        p.write(&[0]);
+        p.commit();
+    }
+
+    #[test]
+    #[cfg_attr(feature = "word-write", ignore = "writes bytes at unaligned positions")]
+    #[cfg(feature = "frame-crc")]
+    fn verified_frames() {
+        let mut b = RingBuffer::new(0, 0);
+        let buf = &[const { UnsafeCell::new(MaybeUninit::uninit()) }; 16];
+        // SAFETY: Test buffer is 16 bytes, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(buf) };
+        write_frame(&mut p, &[1, 2]);
+        write_frame(&mut p, &[3]);
+        // Incomplete frame, not returned until its delimiter is written.
+        p.write(&[4]);
+        p.commit();
+
+        let r = c.read();
+        let mut frames = r.verified_frames();
+        assert_eq!(frames.next().unwrap().bufs(), (&[1, 2][..], &[][..]));
+        assert_eq!(frames.next().unwrap().bufs(), (&[3][..], &[][..]));
+        assert_eq!(frames.next(), None);
+        assert_eq!(frames.discarded(), 0);
+        assert_eq!(frames.consumed(), 11);
+    }
+
+    #[test]
+    #[cfg(feature = "word-write")]
+    #[cfg(feature = "frame-crc")]
+    fn verified_frames_words() {
+        // Bodies of two-word frames.
+        const B: usize = 2 * W - crate::frame::TRAILER_LEN - 1;
+        let mut b = RingBuffer::new(0, 0);
+        let buf = WordBuf::<{ 8 * W }>::new();
+        // SAFETY: Test buffer is 8 words, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(&buf.0) };
+        write_frame(&mut p, &[1; B]);
+        write_frame(&mut p, &[3; B]);
+        // Incomplete frame, not returned until its delimiter is written.
+        p.write(&[4; W]);
+        p.commit();
+
+        let r = c.read();
+        let mut frames = r.verified_frames();
+        assert_eq!(frames.next().unwrap().bufs(), (&[1; B][..], &[][..]));
+        assert_eq!(frames.next().unwrap().bufs(), (&[3; B][..], &[][..]));
+        assert_eq!(frames.next(), None);
+        assert_eq!(frames.discarded(), 0);
+        assert_eq!(frames.consumed(), 4 * W);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "word-write", ignore = "writes bytes at unaligned positions")]
+    #[cfg(feature = "frame-crc")]
+    fn verified_frames_skip_corrupt() {
+        let mut b = RingBuffer::new(0, 0);
+        let buf = &[const { UnsafeCell::new(MaybeUninit::uninit()) }; 16];
+        // SAFETY: Test buffer is 16 bytes, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(buf) };
+        // Trailer of a different body.
+        p.write(&[1, 2]);
+        p.write(&crate::frame::trailer(crate::crc::crc32(&[1, 3])));
+        p.write(&[0]);
+        p.commit();
+        // Too short to hold a trailer.
+        p.write(&[5, 0]);
+        p.commit();
+        write_frame(&mut p, &[3]);
+
+        let r = c.read();
+        let mut frames = r.verified_frames();
+        assert_eq!(frames.next().unwrap().bufs(), (&[3][..], &[][..]));
+        assert_eq!(frames.next(), None);
+        assert_eq!(frames.discarded(), 2);
+        assert_eq!(frames.consumed(), 13);
+    }
+
+    #[test]
+    #[cfg(feature = "word-write")]
+    #[cfg(feature = "frame-crc")]
+    fn verified_frames_skip_corrupt_words() {
+        // Bodies of two-word frames.
+        const B: usize = 2 * W - crate::frame::TRAILER_LEN - 1;
+        let mut b = RingBuffer::new(0, 0);
+        let buf = WordBuf::<{ 8 * W }>::new();
+        // SAFETY: Test buffer is 8 words, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(&buf.0) };
+        // Trailer of a different body.
+        p.write(&[1; B]);
+        p.write(&crate::frame::trailer(crate::crc::crc32(&[1, 3])));
+        p.write(&[0]);
+        p.commit();
+        // Too short to hold a trailer.
+        p.write(&[5, 0]);
+        p.commit();
+        write_frame(&mut p, &[3; B]);
+
+        let r = c.read();
+        let mut frames = r.verified_frames();
+        assert_eq!(frames.next().unwrap().bufs(), (&[3; B][..], &[][..]));
+        assert_eq!(frames.next(), None);
+        assert_eq!(frames.discarded(), 2);
+        assert_eq!(frames.consumed(), 5 * W);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "word-write", ignore = "writes bytes at unaligned positions")]
+    #[cfg(feature = "frame-crc")]
+    fn verified_frames_crossing_end() {
+        let mut b = RingBuffer::new(0, 0);
+        let buf = &[const { UnsafeCell::new(MaybeUninit::uninit()) }; 12];
+        // SAFETY: Test buffer is 12 bytes, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(buf) };
+        write_frame(&mut p, &[1, 2, 3, 4]);
+        c.read().release_all();
+        // The trailer wraps around the end.
+        write_frame(&mut p, &[5, 6]);
+        write_frame(&mut p, &[7]);
+
+        let r = c.read();
+        let mut frames = r.verified_frames();
+        assert_eq!(frames.next().unwrap().bufs(), (&[5, 6][..], &[][..]));
+        assert_eq!(frames.next().unwrap().bufs(), (&[][..], &[7][..]));
+        assert_eq!(frames.consumed(), 11);
+        r.release_all();
+
+        // The body wraps around the end.
+        write_frame(&mut p, &[8, 9, 10, 11, 12, 13]);
+        let r = c.read();
+        let mut frames = r.verified_frames();
+        assert_eq!(
+            frames.next().unwrap().bufs(),
+            (&[8, 9, 10, 11, 12][..], &[13][..])
+        );
+        assert_eq!(frames.next(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "word-write")]
+    #[cfg(feature = "frame-crc")]
+    fn verified_frames_crossing_end_words() {
+        let mut b = RingBuffer::new(7 * W as u32, 7 * W as u32);
+        let buf = WordBuf::<{ 8 * W }>::new();
+        // SAFETY: Test buffer is 8 words, well under i32::MAX / 4.
+        let (mut p, mut c) = unsafe { b.split(&buf.0) };
+        // The trailer wraps around the end.
+        write_frame(&mut p, &[5; W - 1]);
+        write_frame(&mut p, &[7]);
+
+        let r = c.read();
+        let mut frames = r.verified_frames();
+        assert_eq!(frames.next().unwrap().bufs(), (&[5; W - 1][..], &[][..]));
+        assert_eq!(frames.next().unwrap().bufs(), (&[][..], &[7][..]));
+        assert_eq!(frames.consumed(), 2 * W + crate::frame::TRAILER_LEN + 2);
+        r.release_all();
+
+        // The body wraps around the end.
+        const WRITE: usize = W + (crate::frame::TRAILER_LEN + 2).next_multiple_of(W);
+        write_frame(&mut p, &[8; 8 * W - WRITE + 1]);
+        let r = c.read();
+        let mut frames = r.verified_frames();
+        assert_eq!(
+            frames.next().unwrap().bufs(),
+            (&[8; 8 * W - WRITE][..], &[8][..])
+        );
+        assert_eq!(frames.next(), None);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "word-write", ignore = "writes bytes at unaligned positions")]
+    fn recover_after_reset() {
+        let mut region = Region([0; 128]);
+        {
+            let (mut p, _c, status) = recover(&mut region);
+            assert_eq!(status, RecoveryStatus::Reinitialized);
+            p.write(&[1, 2, 3]);
+            p.commit();
+        }
+
+        let (_p, mut c, status) = recover(&mut region);
+        assert_eq!(status, RecoveryStatus::Valid);
+        assert_eq!(c.boot_count(), 1);
+        assert_eq!(c.read().bufs(), (&[1, 2, 3][..], &[][..]));
+    }
+
+    #[test]
+    #[cfg(feature = "word-write")]
+    fn recover_after_reset_words() {
+        let mut region = Region([0; 128]);
+        {
+            let (mut p, _c, status) = recover(&mut region);
+            assert_eq!(status, RecoveryStatus::Reinitialized);
+            p.write(&words(&[1, 2, 3]));
+            p.commit();
+        }
+
+        let (_p, mut c, status) = recover(&mut region);
+        assert_eq!(status, RecoveryStatus::Valid);
+        assert_eq!(c.boot_count(), 1);
+        assert_eq!(c.read().bufs(), (&words(&[1, 2, 3])[..], &[][..]));
+    }
+
+    #[test]
+    #[cfg(feature = "word-write")]
+    fn word_write_pads_frames() {
+        let mut region = Region([0xff; 128]);
+        let (mut p, mut c, _) = recover(&mut region);
+        p.write(&[1]);
+        p.write(&[2, 3]);
+        assert!(p.commit());
+        p.write(&[4; W]);
+        assert!(p.commit());
+        assert_eq!(p.header.write.load(Ordering::Relaxed) as usize, 2 * W);
+
+        let r = c.read();
+        let (buf, _) = r.bufs();
+        assert_eq!(buf[..3], [1, 2, 3]);
+        assert!(buf[3..W].iter().all(|&b| b == 0));
+        assert_eq!(buf[W..], [4; W]);
+    }
+
+    #[test]
+    #[cfg(feature = "word-write")]
+    fn word_write_recover_unaligned() {
+        let mut region = Region([0xff; 128]);
+        {
+            let (mut p, _c, _) = recover(&mut region);
+            p.write(&[1; W]);
+            p.commit();
+            p.write(&[5, 6]);
+            p.commit();
+            // As left by a run without `word-write`, which committed only 5.
+            p.header.write.store(W as u32 + 1, Ordering::Relaxed);
+            p.header.update_checksum();
+        }
+        {
+            let (_p, mut c, status) = recover(&mut region);
+            assert_eq!(status, RecoveryStatus::Valid);
+            let r = c.read();
+            let (buf, _) = r.bufs();
+            assert_eq!(buf.len(), 2 * W);
+            assert_eq!(buf[W..W + 2], [5, 0]);
+            r.release(0);
+
+            // Padding would overwrite the unread data at `read`.
+            c.header.read.store(W as u32 + 2, Ordering::Relaxed);
+            c.header.write.store(W as u32 + 1, Ordering::Relaxed);
+            c.header.update_checksum();
+        }
+        let (p, _c, status) = recover(&mut region);
+        assert_eq!(status, RecoveryStatus::Valid);
+        assert_eq!(p.header.write.load(Ordering::Relaxed) as usize, W);
+    }
+
+    #[test]
+    #[cfg(word64)]
+    fn word64_fields() {
+        let mut region = Region([0xff; 128]);
+        {
+            let (mut p, _c, status) = recover(&mut region);
+            assert_eq!(status, RecoveryStatus::Reinitialized);
+            p.write(&[1; W]);
+            p.commit();
+        }
+        // Each store writes the whole word of a field, with the value in its first 4 bytes.
+        for field in [
+            offset_of!(RingBuffer, write),
+            offset_of!(RingBuffer, checksum),
+        ] {
+            assert_eq!(region.0[field + 4..field + 8], [0; 4]);
+        }
+        let write = offset_of!(RingBuffer, write);
+        assert_eq!(region.0[write..write + 4], (W as u32).to_ne_bytes());
+
+        let (_p, mut c, status) = recover(&mut region);
+        assert_eq!(status, RecoveryStatus::Valid);
+        assert_eq!(c.read().bufs(), (&[1; W][..], &[][..]));
+    }
+
+    #[test]
+    fn recover_firmware_id() {
+        let mut region = Region([0; 128]);
+        {
+            let (_p, mut c, _) = recover(&mut region);
+            assert_eq!(c.swap_firmware_id(0x1234_5678), 0);
+        }
+
+        // A compact header does not keep the firmware identifier.
+        let kept = if cfg!(feature = "compact-header") {
+            0
+        } else {
+            0x1234_5678
+        };
+        let (_p, mut c, status) = recover(&mut region);
+        assert_eq!(status, RecoveryStatus::Valid);
+        assert_eq!(c.swap_firmware_id(0x9abc_def0), kept);
+    }
+
+    #[cfg(feature = "compact-header")]
+    #[test]
+    fn compact_metadata() {
+        let mut region = Region([0; 128]);
+        {
+            let (mut p, mut c, _) = recover(&mut region);
+            c.set_log_level(LogLevel::Error);
+            c.set_reset_reason(3);
+            for _ in 0..33 {
+                p.write(&[0; 128]);
+                assert!(!p.commit());
+            }
+            assert_eq!(p.dropped_frames(), 1);
+        }
+
+        let (_p, c, status) = recover(&mut region);
+        assert_eq!(status, RecoveryStatus::Valid);
+        assert_eq!(c.boot_count(), 1);
+        assert_eq!(c.log_level(), LogLevel::Error);
+        assert_eq!(c.dropped_frames(), 1);
+        assert_eq!(c.header.load_meta(Meta::ResetReason, Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn recover_log_level() {
+        let mut region = Region([0; 128]);
+        {
+            let (p, mut c, _) = recover(&mut region);
+            assert_eq!(c.log_level(), LogLevel::Trace);
+            c.set_log_level(LogLevel::Warn);
+            assert_eq!(p.log_level(), LogLevel::Warn);
+        }
+
+        let (_p, c, status) = recover(&mut region);
+        assert_eq!(status, RecoveryStatus::Valid);
+        assert_eq!(c.log_level(), LogLevel::Warn);
+    }
+
+    #[test]
+    fn recover_flipped_index() {
+        let mut region = Region([0; 128]);
+        {
+            let (mut p, _c, _) = recover(&mut region);
+            p.write(&[1, 2, 3, 4, 5, 6, 7, 0]);
+            p.commit();
+        }
+
+        // Still in bounds, but no longer matching the checksum.
+        region.0[offset_of!(RingBuffer, write)] ^= 16;
+
+        let (_p, mut c, status) = recover(&mut region);
+        assert_eq!(status, RecoveryStatus::IndicesRepaired);
+        assert_eq!(c.boot_count(), 1);
+        assert_eq!(c.read().bufs(), (&[1, 2, 3, 4, 5, 6, 7, 0][..], &[][..]));
+    }
+
+    #[test]
+    fn recover_stale_checksum() {
+        let mut region = Region([0; 128]);
+        let checksum = offset_of!(RingBuffer, checksum);
+        let stale = {
+            let (mut p, _c, _) = recover(&mut region);
+            p.write(&[1, 2, 3, 4, 5, 6, 7, 0]);
+            p.commit();
+            let stale = region.0[checksum..checksum + 4].to_vec();
+            p.write(&[8, 9, 10, 0]);
+            p.commit();
+            stale
+        };
+
+        // A reset before the checksum update after a commit loses the newest frame.
+        region.0[checksum..checksum + 4].copy_from_slice(&stale);
+        let stale = {
+            let (_p, mut c, status) = recover(&mut region);
+            assert_eq!(status, RecoveryStatus::IndicesRepaired);
+            let stale = region.0[checksum..checksum + 4].to_vec();
+            let grant = c.read();
+            assert_eq!(grant.bufs(), (&[1, 2, 3, 4, 5, 6, 7, 0][..], &[][..]));
+            grant.release(2);
+            stale
+        };
+
+        // After a release, it keeps the released data from being read again.
+        region.0[checksum..checksum + 4].copy_from_slice(&stale);
+        let (_p, mut c, status) = recover(&mut region);
+        assert_eq!(status, RecoveryStatus::IndicesRepaired);
+        assert_eq!(c.read().bufs(), (&[3, 4, 5, 6, 7, 0][..], &[][..]));
+    }
+
+    #[test]
+    fn recover_discarded() {
+        let mut region = Region([0; 128]);
+        {
+            let (mut p, _c, _) = recover(&mut region);
+            p.write(&[1, 2, 3, 0]);
+            p.commit();
+        }
+
+        // No index matches the checksum.
+        #[cfg(not(feature = "compact-header"))]
+        let meta = offset_of!(RingBuffer, dropped);
+        // The lowest byte of a compact header holds the boot count.
+        #[cfg(feature = "compact-header")]
+        let meta = offset_of!(RingBuffer, header);
+        region.0[meta] ^= 1;
+
+        let (_p, mut c, status) = recover(&mut region);
+        assert_eq!(status, RecoveryStatus::Discarded);
+        assert_eq!(c.read().bufs(), (&[][..], &[][..]));
+    }
+
+    #[test]
+    fn recover_corrupt_header() {
+        let mut region = Region([0; 128]);
+        {
+            let (mut p, _c, _) = recover(&mut region);
+            p.write(&[1, 2, 3]);
+            p.commit();
+        }
+
+        // The magic is in the upper bits of a compact header.
+        region.0[offset_of!(RingBuffer, header) + size_of::<Magic>() - 1] ^= 1;
+
+        let (_p, mut c, status) = recover(&mut region);
+        assert_eq!(status, RecoveryStatus::Reinitialized);
+        assert_eq!(c.boot_count(), 0);
+        assert_eq!(c.read().bufs(), (&[][..], &[][..]));
+    }
 }
@@ -0,0 +1,113 @@
+//! Extra outputs for the encoded defmt stream, registered by the application.
+
+use core::cell::UnsafeCell;
+
+/// Maximum number of sinks that can be registered with [`add_sink`].
+pub const MAX_SINKS: usize = 4;
+
+/// An extra output for the logger, e.g. a UART or USB mirror of the log stream.
+///
+/// Sinks receive the same encoded bytes as the ring buffer and RTT, including frames filtered
+/// from those by the runtime log levels, and frames written before [`crate::init`].
+pub trait Sink: Sync {
+    /// Writes a chunk of the encoded stream.
+    ///
+    /// Called inside the logger's critical section, so this must not block for long and must
+    /// not log. Chunks are not aligned to frames, a frame ends with a zero byte.
+    fn write(&self, bytes: &[u8]);
+
+    /// Waits until all written bytes have been sent, called from `defmt::flush`.
+    ///
+    /// Also called inside the logger's critical section. Does nothing by default.
+    fn flush(&self) {}
+}
+
+/// Error returned by [`add_sink`] when [`MAX_SINKS`] sinks are already registered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct TooManySinks;
+
+struct Sinks(UnsafeCell<[Option<&'static dyn Sink>; MAX_SINKS]>);
+
+// SAFETY: The sinks are only accessed in critical sections, and are `Sync` themselves.
+unsafe impl Sync for Sinks {}
+
+static SINKS: Sinks = Sinks(UnsafeCell::new([None; MAX_SINKS]));
+
+/// Registers `sink` to receive everything logged from now on.
+///
+/// Sinks cannot be removed. Use interior mutability in the sink to turn it off.
+///
+/// # Errors
+///
+/// Returns [`TooManySinks`] if [`MAX_SINKS`] sinks are already registered.
+pub fn add_sink(sink: &'static dyn Sink) -> Result<(), TooManySinks> {
+    critical_section::with(|_| {
+        // SAFETY: The critical section ensures exclusive access to `SINKS`.
+        let sinks = unsafe { &mut *SINKS.0.get() };
+        let slot = sinks
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(TooManySinks)?;
+        *slot = Some(sink);
+        Ok(())
+    })
+}
+
+/// Writes `bytes` to all registered sinks.
+///
+/// # Safety
+///
+/// Must be called from within a critical section.
+#[inline]
+pub(crate) unsafe fn write(bytes: &[u8]) {
+    // SAFETY: The caller guarantees we're in a critical section, so `SINKS` is not modified.
+    for sink in unsafe { &*SINKS.0.get() }.iter().flatten() {
+        sink.write(bytes);
+    }
+}
+
+/// Flushes all registered sinks.
+///
+/// # Safety
+///
+/// Must be called from within a critical section.
+#[inline]
+pub(crate) unsafe fn flush() {
+    // SAFETY: The caller guarantees we're in a critical section, so `SINKS` is not modified.
+    for sink in unsafe { &*SINKS.0.get() }.iter().flatten() {
+        sink.flush();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Counter(AtomicUsize);
+
+    impl Sink for Counter {
+        fn write(&self, bytes: &[u8]) {
+            self.0.fetch_add(bytes.len(), Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(
+        feature = "qemu-test",
+        ignore = "logs via semihosting, which needs QEMU"
+    )]
+    fn add_sinks() {
+        static COUNTER: Counter = Counter(AtomicUsize::new(0));
+        add_sink(&COUNTER).unwrap();
+
+        defmt::println!("to the sink");
+        assert_ne!(COUNTER.0.load(Ordering::Relaxed), 0);
+
+        // One slot is taken above.
+        for _ in 1..MAX_SINKS {
+            add_sink(&COUNTER).unwrap();
+        }
+        assert_eq!(add_sink(&COUNTER), Err(TooManySinks));
+    }
+}
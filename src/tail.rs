@@ -0,0 +1,225 @@
+//! Keep the last bytes of the log stream in a few retained registers.
+//!
+//! Some devices have no RAM that is retained across a reset or through standby, only a
+//! handful of battery-backed registers, e.g. the RTC backup registers of an STM32. These are
+//! too small for the ring buffer, and often only accept 32-bit accesses. A [`TailLog`]
+//! overwrites the oldest bytes in such registers, so they always hold the end of the log
+//! stream, such as a panic message.
+//!
+//! ```ignore
+//! use defmt_persist::tail::TailLog;
+//!
+//! // SAFETY: The 32 RTC backup registers are only used for the log.
+//! static TAIL: TailLog = unsafe { TailLog::new(0x4000_2850 as *mut u32, 32) };
+//!
+//! let mut buf = [0; TailLog::max_capacity(32)];
+//! let len = TAIL.recover(&mut buf);
+//! // Send `buf[..len]` somewhere, it decodes like the ring buffer contents.
+//! defmt_persist::add_sink(&TAIL).unwrap();
+//! ```
+
+use crate::sink::Sink;
+use core::ptr;
+
+/// Marks the first word as a tail log header, in the upper 16 bits.
+const MAGIC: u32 = 0xd7a1_0000;
+/// Set in the header once the data has wrapped around.
+const WRAPPED: u32 = 0x8000;
+/// Mask of the write position in the header.
+const POS: u32 = 0x7fff;
+
+/// A [`Sink`] that keeps the last bytes of the log stream in retained 32-bit words.
+///
+/// The first word holds a header with the write position, the others hold the data. Every
+/// access is a 32-bit volatile read or write. The logger does not persist anything else in
+/// this mode, so the boot count, reset reason and dropped count are not kept.
+pub struct TailLog {
+    words: *mut u32,
+    len: usize,
+}
+
+// SAFETY: The words are only accessed in critical sections.
+unsafe impl Sync for TailLog {}
+
+impl TailLog {
+    /// Creates a tail log over `len` retained words starting at `words`.
+    ///
+    /// # Safety
+    ///
+    /// `words` must be valid for 32-bit volatile reads and writes of `len` words for the
+    /// lifetime of the tail log, aligned, and not accessed by anything else.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` is less than 2, or if the data does not fit in 32767 bytes.
+    pub const unsafe fn new(words: *mut u32, len: usize) -> Self {
+        assert!(len >= 2, "a tail log needs at least 2 words");
+        assert!(
+            Self::max_capacity(len) <= POS as usize,
+            "a tail log holds at most 32767 bytes"
+        );
+        Self { words, len }
+    }
+
+    /// Returns the number of bytes kept in `len` words, for sizing a recovery buffer.
+    pub const fn max_capacity(len: usize) -> usize {
+        (len - 1) * 4
+    }
+
+    /// Returns the number of bytes kept by this tail log.
+    pub const fn capacity(&self) -> usize {
+        Self::max_capacity(self.len)
+    }
+
+    /// Copies the bytes kept from the previous run to `out`, oldest first, and clears them.
+    ///
+    /// Returns the number of bytes copied, which always end with a complete frame. Once the
+    /// log has wrapped around, the oldest frame is cut, so the bytes up to its end are
+    /// skipped. The same is done if `out` is too small, keeping the newest frames. Nothing is
+    /// recovered if the header is invalid, e.g. after a loss of the backup supply.
+    ///
+    /// Call this before registering the tail log with [`crate::add_sink`].
+    pub fn recover(&self, out: &mut [u8]) -> usize {
+        critical_section::with(|_| {
+            let mut len = 0;
+            if let Some((pos, wrapped)) = self.position() {
+                let (mut start, end) = if wrapped {
+                    (pos, pos + self.capacity())
+                } else {
+                    (0, pos)
+                };
+                let cut = wrapped || end - start > out.len();
+                start = start.max(end.saturating_sub(out.len()));
+                if cut {
+                    // Skip to the first frame boundary.
+                    while start < end && self.byte(start % self.capacity()) != 0 {
+                        start += 1;
+                    }
+                    start = (start + 1).min(end);
+                }
+                for (i, out) in (start..end).zip(out.iter_mut()) {
+                    *out = self.byte(i % self.capacity());
+                    len += 1;
+                }
+                // Drop a frame cut by the reset.
+                len = out[..len]
+                    .iter()
+                    .rposition(|&b| b == 0)
+                    .map_or(0, |i| i + 1);
+            }
+            for i in 1..self.len {
+                self.write_word(i, 0);
+            }
+            self.write_word(0, MAGIC);
+            len
+        })
+    }
+
+    /// Returns the write position and whether the data has wrapped around, or `None` if the
+    /// header is invalid.
+    fn position(&self) -> Option<(usize, bool)> {
+        let header = self.read(0);
+        let pos = (header & POS) as usize;
+        (header & !(WRAPPED | POS) == MAGIC && pos < self.capacity())
+            .then_some((pos, header & WRAPPED != 0))
+    }
+
+    /// Reads word `i`.
+    fn read(&self, i: usize) -> u32 {
+        // SAFETY: `new` requires `words` to be valid for `len` words, and `i < len`.
+        unsafe { ptr::read_volatile(self.words.add(i)) }
+    }
+
+    /// Writes word `i`.
+    fn write_word(&self, i: usize, value: u32) {
+        // SAFETY: `new` requires `words` to be valid for `len` words, and `i < len`.
+        unsafe { ptr::write_volatile(self.words.add(i), value) }
+    }
+
+    /// Reads data byte `i`, the words are packed little-endian.
+    fn byte(&self, i: usize) -> u8 {
+        (self.read(1 + i / 4) >> (i % 4 * 8)) as u8
+    }
+}
+
+impl Sink for TailLog {
+    fn write(&self, bytes: &[u8]) {
+        // Start over if the header was not recovered.
+        let (mut pos, mut wrapped) = self.position().unwrap_or((0, false));
+        // Only keep what fits, the rest would be overwritten anyway.
+        let skip = bytes.len().saturating_sub(self.capacity());
+        if skip > 0 {
+            pos = (pos + skip) % self.capacity();
+            wrapped = true;
+        }
+        for &byte in &bytes[skip..] {
+            let word = 1 + pos / 4;
+            let shift = pos % 4 * 8;
+            self.write_word(
+                word,
+                self.read(word) & !(0xff << shift) | u32::from(byte) << shift,
+            );
+            pos += 1;
+            if pos == self.capacity() {
+                pos = 0;
+                wrapped = true;
+            }
+        }
+        let wrapped = if wrapped { WRAPPED } else { 0 };
+        self.write_word(0, MAGIC | wrapped | pos as u32);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn recover() {
+        let mut words = [0xdead_beef; 4];
+        // SAFETY: `words` outlives the tail log and is only accessed through it.
+        let tail = unsafe { TailLog::new(words.as_mut_ptr(), words.len()) };
+        let mut buf = [0; 12];
+        assert_eq!(tail.capacity(), 12);
+
+        // Garbage is not recovered.
+        assert_eq!(tail.recover(&mut buf), 0);
+        assert_eq!(tail.recover(&mut buf), 0);
+
+        tail.write(&[1, 2, 0]);
+        tail.write(&[3, 0]);
+        assert_eq!(tail.recover(&mut buf), 5);
+        assert_eq!(buf[..5], [1, 2, 0, 3, 0]);
+        assert_eq!(tail.recover(&mut buf), 0);
+
+        // The last frame was cut by a reset.
+        tail.write(&[1, 0, 2]);
+        assert_eq!(tail.recover(&mut buf), 2);
+        assert_eq!(buf[..2], [1, 0]);
+
+        // Too small for the first frame.
+        tail.write(&[1, 2, 0, 3, 4, 0]);
+        assert_eq!(tail.recover(&mut buf[..4]), 3);
+        assert_eq!(buf[..3], [3, 4, 0]);
+    }
+
+    #[test]
+    fn wrap() {
+        let mut words = [0; 3];
+        // SAFETY: `words` outlives the tail log and is only accessed through it.
+        let tail = unsafe { TailLog::new(words.as_mut_ptr(), words.len()) };
+        let mut buf = [0; 8];
+        tail.recover(&mut buf);
+
+        tail.write(&[1, 2, 0, 3, 4, 5, 0]);
+        tail.write(&[6, 7, 8, 0]);
+        // The oldest frame is cut, 3 is left of it.
+        assert_eq!(tail.recover(&mut buf), 4);
+        assert_eq!(buf[..4], [6, 7, 8, 0]);
+
+        // Longer than the capacity at once.
+        tail.write(&[1, 0, 2, 0, 3, 0, 4, 0, 5, 0]);
+        assert_eq!(tail.recover(&mut buf), 6);
+        assert_eq!(buf[..6], [3, 0, 4, 0, 5, 0]);
+    }
+}
@@ -42,6 +42,9 @@ fn main() -> ! {
     let metadata = defmt_persist::init().unwrap();
     let mut consumer = metadata.consumer;
 
+    // Drain the session marker, so the reader only waits for the writer's messages.
+    drain_to_uart(&mut consumer);
+
     block_on(join(writer_task(), reader_task(&mut consumer)));
 
     exit_success();
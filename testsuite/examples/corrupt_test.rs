@@ -17,7 +17,7 @@ fn main() -> ! {
     let metadata = defmt_persist::init().unwrap();
     let mut consumer = metadata.consumer;
 
-    if !consumer.is_empty() {
+    if metadata.recovered_logs_len != 0 {
         // Phase 3: Buffer was recovered (valid snapshot loaded).
         drain_to_uart(&mut consumer);
     } else {
@@ -20,7 +20,7 @@ fn main() -> ! {
     let metadata = defmt_persist::init().unwrap();
     let mut consumer = metadata.consumer;
 
-    if !consumer.is_empty() {
+    if metadata.recovered_logs_len != 0 {
         // Phase 2: Read recovered logs and output via UART0.
         defmt::info!("Some text during second run after a panic.");
         drain_to_uart(&mut consumer);
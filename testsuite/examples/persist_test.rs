@@ -16,7 +16,7 @@ fn main() -> ! {
     let metadata = defmt_persist::init().unwrap();
     let mut consumer = metadata.consumer;
 
-    if !consumer.is_empty() {
+    if metadata.recovered_logs_len != 0 {
         // Phase 2: Read recovered logs.
         defmt::info!("This message will only be in the second run!");
         drain_to_uart(&mut consumer);
@@ -4,19 +4,24 @@ use std::fs;
 use std::path::PathBuf;
 
 use anyhow::{Context, Result};
-use defmt_persist::offsets;
+use defmt_persist::{RecoveryStatus, host, offsets};
 use tempfile::NamedTempFile;
 
 use crate::defmt;
 use crate::qemu::{MemoryLoad, run_qemu};
 use crate::runner::{FAIL, PASS, PERSIST_ADDR, RunOptions};
 
+/// Logged by the example when it finds an empty buffer.
+const FRESH_MESSAGE: &str = "corrupt test: fresh buffer";
+
 /// Corruption scenario flags.
 #[derive(Debug, Clone, Copy)]
 struct CorruptFlags {
     header: bool,
     read: bool,
     write: bool,
+    /// Move the read index past the write index, keeping it in bounds.
+    shift: bool,
 }
 
 impl CorruptFlags {
@@ -31,6 +36,9 @@ impl CorruptFlags {
         if self.write {
             parts.push("write");
         }
+        if self.shift {
+            parts.push("shift");
+        }
         if parts.is_empty() {
             "none".to_string()
         } else {
@@ -40,51 +48,71 @@ impl CorruptFlags {
 
     /// Returns true if any corruption is present.
     fn any(&self) -> bool {
-        self.header || self.read || self.write
+        self.header || self.read || self.write || self.shift
     }
 
-    /// A list of all combinations.
-    fn all_combinations() -> [Self; 8] {
+    /// Returns true if the logs are expected to be discarded. A single corrupt index is
+    /// repaired from the header checksum.
+    fn discards(&self) -> bool {
+        self.header || (self.read && self.write)
+    }
+
+    /// A list of all header/read/write combinations, plus an in-bounds index shift.
+    fn all_combinations() -> [Self; 9] {
         [
             CorruptFlags {
                 header: false,
                 read: false,
                 write: false,
+                shift: false,
             },
             CorruptFlags {
                 header: true,
                 read: false,
                 write: false,
+                shift: false,
             },
             CorruptFlags {
                 header: false,
                 read: true,
                 write: false,
+                shift: false,
             },
             CorruptFlags {
                 header: false,
                 read: false,
                 write: true,
+                shift: false,
             },
             CorruptFlags {
                 header: true,
                 read: true,
                 write: false,
+                shift: false,
             },
             CorruptFlags {
                 header: true,
                 read: false,
                 write: true,
+                shift: false,
             },
             CorruptFlags {
                 header: false,
                 read: true,
                 write: true,
+                shift: false,
             },
             CorruptFlags {
                 header: true,
                 read: true,
                 write: true,
+                shift: false,
+            },
+            CorruptFlags {
+                header: false,
+                read: false,
+                write: false,
+                shift: true,
             },
         ]
     }
@@ -95,7 +123,8 @@ fn apply_corruption(snapshot: &[u8], flags: CorruptFlags) -> Vec<u8> {
     let mut corrupted = snapshot.to_vec();
 
     if flags.header {
-        corrupted[offsets::HEADER] = 0;
+        // The last byte of the header is part of the magic in every layout.
+        corrupted[offsets::READ - 1] = 0;
     }
 
     if flags.read {
@@ -108,12 +137,24 @@ fn apply_corruption(snapshot: &[u8], flags: CorruptFlags) -> Vec<u8> {
         corrupted[offsets::WRITE + offsets::INDEX_SIZE - 1] = 0xff;
     }
 
+    if flags.shift {
+        // Set the bit above the highest bit of `write`, only detectable by the header checksum.
+        // Phase 1 starts from an empty buffer, so `read` is 0 and `write` is far from the end.
+        let write = u32::from_le_bytes(
+            corrupted[offsets::WRITE..offsets::WRITE + offsets::INDEX_SIZE]
+                .try_into()
+                .unwrap(),
+        );
+        corrupted[offsets::READ..offsets::READ + offsets::INDEX_SIZE]
+            .copy_from_slice(&(1u32 << (u32::BITS - write.leading_zeros())).to_le_bytes());
+    }
+
     corrupted
 }
 
 /// Run a corruption test.
 ///
-/// Tests all 8 combinations of header/read/write corruption.
+/// Tests all 8 combinations of header/read/write corruption, and an in-bounds index shift.
 pub fn run_corrupt(elf_path: &PathBuf, opts: &RunOptions) -> Result<bool> {
     // Phase 1: Run normally, capture persist region.
     println!("Phase 1: Normal run to capture persist region...");
@@ -152,6 +193,24 @@ pub fn run_corrupt(elf_path: &PathBuf, opts: &RunOptions) -> Result<bool> {
 
         println!("  Scenario {}: corrupt={}", i + 1, flags.name());
 
+        // The host-side parser must agree with `init` on whether the logs are recovered.
+        let status = host::parse(&corrupted)
+            .ok()
+            .map(|dump| dump.recovery_status);
+        let expected = if flags.header {
+            None
+        } else if flags.discards() {
+            Some(RecoveryStatus::Discarded)
+        } else if flags.any() {
+            Some(RecoveryStatus::IndicesRepaired)
+        } else {
+            Some(RecoveryStatus::Valid)
+        };
+        if status != expected {
+            println!("    {FAIL}: host parser disagrees (status: {status:?})");
+            all_passed = false;
+        }
+
         let result = run_qemu(
             elf_path,
             Some(MemoryLoad {
@@ -169,10 +228,14 @@ pub fn run_corrupt(elf_path: &PathBuf, opts: &RunOptions) -> Result<bool> {
             print!("{result_uart0}");
         }
 
-        // No corruption: recovery path (semihosting empty).
-        // Any corruption: fresh path (semihosting has "fresh buffer" message).
-        let passed = if flags.any() {
-            if !result_semihosting.is_empty() && result_uart0 == phase1_uart0 {
+        // No corruption or a repaired index: recovery path (UART starts with the recovered
+        // phase 1 logs).
+        // Other corruption: fresh path (semihosting has "fresh buffer" message).
+        // The session marker is always logged, and its boot count depends on whether only
+        // the indexes or also the header were corrupted, so it is not compared to phase 1.
+        let fresh = result_semihosting.contains(FRESH_MESSAGE);
+        let passed = if flags.discards() {
+            if fresh && result_uart0 == result_semihosting {
                 println!("    {PASS}: buffer reinitialized");
                 true
             } else {
@@ -184,7 +247,7 @@ pub fn run_corrupt(elf_path: &PathBuf, opts: &RunOptions) -> Result<bool> {
                 false
             }
         } else {
-            if result_semihosting.is_empty() && !result_uart0.is_empty() {
+            if !fresh && result_uart0.starts_with(&phase1_uart0) {
                 println!("    {PASS}: recovered data");
                 true
             } else {